@@ -1,55 +1,988 @@
+use crate::backtrace::fold_backtraces;
+use crate::config::{Config, Keymap, Theme};
+use crate::editor::Location;
+use crate::events::{
+    find_location, parse_panic, running_binary, same_target, Diagnostic, RunnerEvent, TestResult,
+};
+use crate::failure::{parse_failure, render};
+use crate::history::{baselines, flakiness, History, Revision, RunRecord, TestRecord};
+use crate::test_runner::{format_duration, Backtrace, Job, RunSpec};
+use crate::tree::{build_tree, visible_rows, NodeKind, TestRef, TreeNode, TreeRow};
+use crate::workspace::{Target, Workspace};
+use ansi_parser::{AnsiParser, Output};
+use chrono::Local;
 use crossterm::event::KeyCode;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use fuzzy_matcher::FuzzyMatcher;
 use log::debug;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
-use std::path::PathBuf;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum TestStatus {
+    #[default]
+    Pending,
+    Running,
+    Passed,
+    Failed,
+    Ignored,
+    TimedOut,
+}
+
+impl TestStatus {
+    /// Combines the statuses of several tests into one for a file or module:
+    /// any failure wins, then anything still running or not yet run.
+    pub fn aggregate(statuses: impl Iterator<Item = TestStatus>) -> TestStatus {
+        let statuses: Vec<TestStatus> = statuses.collect();
+        let any = |wanted: &[TestStatus]| statuses.iter().any(|status| wanted.contains(status));
+
+        if any(&[TestStatus::Failed, TestStatus::TimedOut]) {
+            TestStatus::Failed
+        } else if any(&[TestStatus::Running]) {
+            TestStatus::Running
+        } else if statuses.is_empty() || any(&[TestStatus::Pending]) {
+            TestStatus::Pending
+        } else if any(&[TestStatus::Passed]) {
+            TestStatus::Passed
+        } else {
+            TestStatus::Ignored
+        }
+    }
+}
+
+pub struct TestCase {
+    pub name: String,
+    pub attributes: Vec<String>,
+    pub status: TestStatus,
+    /// How long the test ran the last time it reported a time.
+    pub duration: Option<Duration>,
+    /// Line and column of the test function, when found in the source.
+    pub location: Option<(usize, usize)>,
+    /// Where the test panicked the last time it failed.
+    pub panic: Option<Location>,
+    /// Captured output and result of the latest run of the test.
+    pub output: String,
+    /// Share of the recorded runs of unchanged sources in which the outcome
+    /// flipped, for tests that ever flipped.
+    pub flakiness: Option<f64>,
+    /// Passed and total runs of the latest stress run.
+    pub stress: Option<(usize, usize)>,
+    /// Median duration of the test in the recorded runs before the latest.
+    pub baseline: Option<Duration>,
+}
+
+/// A test counts as regressed when it takes this many times its baseline,
+/// and at least `REGRESSION_MIN` longer, so that jitter in fast tests does
+/// not count.
+const REGRESSION_FACTOR: f64 = 2.0;
+const REGRESSION_MIN: Duration = Duration::from_millis(10);
+
+impl TestCase {
+    pub fn is_ignored(&self) -> bool {
+        self.attributes.iter().any(|attr| attr == "ignore")
+    }
+
+    /// The latest duration as a multiple of the baseline.
+    pub fn slowdown(&self) -> Option<f64> {
+        let (duration, baseline) = (self.duration?, self.baseline?);
+        Some(duration.as_secs_f64() / baseline.as_secs_f64().max(1e-6))
+    }
+
+    /// Whether the test got significantly slower than it used to be.
+    pub fn is_regressed(&self) -> bool {
+        match (self.duration, self.baseline) {
+            (Some(duration), Some(baseline)) => {
+                duration >= baseline + REGRESSION_MIN
+                    && duration.as_secs_f64() >= baseline.as_secs_f64() * REGRESSION_FACTOR
+            }
+            _ => false,
+        }
+    }
+}
 
 pub struct TestInfo {
     pub path: PathBuf,
-    pub tests: Vec<String>,
+    pub package: String,
+    pub target: Target,
+    pub tests: Vec<TestCase>,
+}
+
+/// How the test list is discovered.
+#[derive(Clone, Copy, PartialEq)]
+pub enum DiscoveryMode {
+    /// Parse the source files for test functions.
+    Source,
+    /// Build the test binaries and ask them for their tests.
+    List,
+}
+
+impl fmt::Display for DiscoveryMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DiscoveryMode::Source => write!(f, "source"),
+            DiscoveryMode::List => write!(f, "list"),
+        }
+    }
+}
+
+/// How the slowest-tests view is sorted.
+#[derive(Clone, Copy, PartialEq)]
+pub enum SlowestOrder {
+    Duration,
+    /// By how much slower the tests got than their baseline.
+    Slowdown,
+}
+
+impl fmt::Display for SlowestOrder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SlowestOrder::Duration => write!(f, "duration"),
+            SlowestOrder::Slowdown => write!(f, "slowdown"),
+        }
+    }
+}
+
+/// Work requested by a key press that the main loop has to carry out.
+pub enum Action {
+    None,
+    Rescan,
+    /// Cancels every unfinished job.
+    CancelAll,
+    CancelJob(usize),
+    RemoveJob(usize),
+    OpenEditor(Location),
+    /// Runs the specs one after another as a single run.
+    Run(Vec<RunSpec>),
+    /// Runs the spec many times to see how reliably it passes.
+    Stress(RunSpec),
+    /// Runs later jobs with another `RUST_BACKTRACE` setting.
+    SetBacktrace(Backtrace),
+}
+
+/// An occurrence of the search text in the output, in characters of the
+/// line without its ANSI escapes.
+pub struct SearchMatch {
+    pub line: usize,
+    pub start: usize,
+    pub len: usize,
+}
+
+/// What the output pane shows.
+#[derive(Clone, Copy, PartialEq)]
+pub enum OutputSource {
+    /// Messages that belong to no run, such as those of rescans.
+    Log,
+    Job(usize),
+    /// The latest captured output of a test.
+    Test(TestRef),
+    /// The run selected in the history view, or its comparison with the
+    /// marked run.
+    History,
+    /// The panic message and assertion diff of a failed test.
+    Failure(TestRef),
+}
+
+pub struct RunningTest {
+    pub job: usize,
+    pub test: TestRef,
+    /// The outcome the test had before the job started.
+    pub previous: TestStatus,
 }
 
 pub struct App {
+    pub workspace: Option<Workspace>,
+    /// The only package shown and run, when one was picked on the command
+    /// line.
+    pub package: Option<String>,
     pub test_info: Vec<TestInfo>,
+    pub tree: Option<TreeNode>,
+    pub expanded: HashSet<String>,
+    pub last_run: Vec<RunSpec>,
+    /// Tests covered by the jobs in progress, which result lines are matched
+    /// against.
+    pub running: Vec<RunningTest>,
+    /// The test binary each job is running, from cargo's `Running` lines.
+    pub binaries: HashMap<usize, String>,
+    /// The runner's job list as of the last update.
+    pub jobs: Vec<Job>,
+    pub selected_job: usize,
+    /// Compiler diagnostics from building the tests of the current run.
+    pub diagnostics: Vec<Diagnostic>,
+    /// Whether the Tests pane lists the diagnostics instead of tests.
+    pub show_diagnostics: bool,
+    pub selected_diagnostic: usize,
+    /// Whether the Tests pane lists every timed test, slowest first,
+    /// instead of the tests of the selected node.
+    pub show_slowest: bool,
+    pub slowest_order: SlowestOrder,
+    /// Fuzzy filter over test names, and whether it is being typed.
+    pub filter: String,
+    pub editing_filter: bool,
+    /// Matched character positions of every test that passes the filter;
+    /// `None` when there is no filter.
+    pub filter_matches: Option<HashMap<TestRef, Vec<usize>>>,
+    /// Search in the output pane, and whether it is being typed.
+    pub search: String,
+    pub editing_search: bool,
+    pub search_matches: Vec<SearchMatch>,
+    pub current_match: usize,
     pub selected_index: usize,
     pub active_pane: usize,
     pub selected_test: usize,
-    pub test_output: String,
+    pub output_source: OutputSource,
+    pub log: String,
+    /// Output of every job still in the job list.
+    pub job_output: HashMap<usize, String>,
+    /// Past runs of the workspace, loaded once it is known.
+    pub history: Option<History>,
+    /// Runs of the jobs in progress, saved to the history when they finish.
+    pub recording: HashMap<usize, RunRecord>,
+    /// Every outcome reported so far by the stress runs in progress.
+    pub stress_outcomes: HashMap<usize, Vec<(TestRef, TestStatus, Option<Duration>)>>,
+    /// Whether the first pane lists past runs instead of the test tree.
+    pub show_history: bool,
+    /// Index into the history list, which shows the newest run first.
+    pub selected_run: usize,
+    /// The run marked to compare the selected run against.
+    pub compare_base: Option<String>,
+    /// Text of the history and failure views.
+    view_text: String,
+    /// The text of the output source as shown, with its backtraces folded.
+    display_text: String,
+    /// The `RUST_BACKTRACE` setting of new jobs.
+    pub backtrace: Backtrace,
+    /// Whether backtraces show their std, core and test harness frames.
+    pub expand_backtraces: bool,
+    /// The directory of every workspace crate by the name its symbols use.
+    crate_dirs: HashMap<String, PathBuf>,
+    keymap: Keymap,
+    pub theme: Theme,
+    /// Whether compiler warnings are listed and shown in the output.
+    pub show_warnings: bool,
     pub watch_mode: bool,
+    pub discovery_mode: DiscoveryMode,
     pub output_scroll: usize,
     pub output_height: usize,
     pub total_output_lines: usize,
+    /// The output line of the location `e` opened last, so that pressing it
+    /// again opens the next one on screen.
+    pub opened_line: Option<usize>,
     pub should_quit: bool,
 }
 
 impl App {
-    pub fn new() -> Self {
+    pub fn new(config: &Config) -> Self {
         App {
+            workspace: None,
+            package: None,
             test_info: Vec::new(),
+            tree: None,
+            expanded: HashSet::new(),
+            last_run: Vec::new(),
+            running: Vec::new(),
+            binaries: HashMap::new(),
+            jobs: Vec::new(),
+            selected_job: 0,
+            diagnostics: Vec::new(),
+            show_diagnostics: false,
+            selected_diagnostic: 0,
+            show_slowest: false,
+            slowest_order: SlowestOrder::Duration,
+            filter: String::new(),
+            editing_filter: false,
+            filter_matches: None,
+            search: String::new(),
+            editing_search: false,
+            search_matches: Vec::new(),
+            current_match: 0,
             selected_index: 0,
             active_pane: 0,
             selected_test: 0,
-            test_output: String::new(),
+            output_source: OutputSource::Log,
+            log: String::new(),
+            job_output: HashMap::new(),
+            history: None,
+            recording: HashMap::new(),
+            stress_outcomes: HashMap::new(),
+            show_history: false,
+            selected_run: 0,
+            compare_base: None,
+            view_text: String::new(),
+            display_text: String::new(),
+            backtrace: Backtrace::from_env(),
+            expand_backtraces: false,
+            crate_dirs: HashMap::new(),
+            keymap: config.keymap.clone(),
+            theme: config.theme,
+            show_warnings: config.show_warnings,
             watch_mode: false,
+            discovery_mode: DiscoveryMode::Source,
             output_scroll: 0,
             should_quit: false,
             output_height: 0,
+            opened_line: None,
             total_output_lines: 0,
         }
     }
 
-    pub fn handle_input(&mut self, key: KeyCode) -> Result<bool, Box<dyn Error>> {
+    pub fn handle_input(&mut self, key: KeyCode) -> Result<Action, Box<dyn Error>> {
+        if self.editing_filter {
+            match key {
+                KeyCode::Esc => {
+                    self.editing_filter = false;
+                    self.set_filter(String::new());
+                }
+                KeyCode::Enter => self.editing_filter = false,
+                KeyCode::Backspace => {
+                    let mut filter = self.filter.clone();
+                    filter.pop();
+                    self.set_filter(filter);
+                }
+                KeyCode::Char(c) => self.set_filter(format!("{}{}", self.filter, c)),
+                _ => {}
+            }
+            return Ok(Action::None);
+        }
+
+        if self.editing_search {
+            match key {
+                KeyCode::Esc => {
+                    self.editing_search = false;
+                    self.set_search(String::new());
+                }
+                KeyCode::Enter => self.editing_search = false,
+                KeyCode::Backspace => {
+                    let mut search = self.search.clone();
+                    search.pop();
+                    self.set_search(search);
+                }
+                KeyCode::Char(c) => self.set_search(format!("{}{}", self.search, c)),
+                _ => {}
+            }
+            return Ok(Action::None);
+        }
+
+        let Some(key) = self.keymap.translate(key, self.active_pane == 3) else {
+            return Ok(Action::None);
+        };
         match key {
+            KeyCode::Char('/') if self.active_pane == 3 => self.editing_search = true,
+            KeyCode::Char('n') if self.active_pane == 3 => self.jump_to_match(true),
+            KeyCode::Char('N') if self.active_pane == 3 => self.jump_to_match(false),
+            KeyCode::Esc if self.active_pane == 3 && !self.search.is_empty() => {
+                self.set_search(String::new())
+            }
+            KeyCode::Char('d') | KeyCode::Char('u') if self.active_pane == 3 => {
+                self.navigate_list(key)
+            }
+            KeyCode::Char('/') => self.editing_filter = true,
+            KeyCode::Esc if !self.filter.is_empty() => self.set_filter(String::new()),
             KeyCode::Char('q') => self.should_quit = true,
             KeyCode::Char('w') => self.toggle_watch_mode(),
-            KeyCode::Char('r') => return Ok(true), // Signal to rescan tests
-            KeyCode::Enter => return Ok(true),     // Signal to run test
+            KeyCode::Char('m') => {
+                self.toggle_discovery_mode();
+                return Ok(Action::Rescan);
+            }
+            KeyCode::Char('r') => return Ok(Action::Rescan),
+            KeyCode::Char('H') => self.toggle_history(),
+            KeyCode::Char('c') if self.showing_history() => self.toggle_compare_base(),
+            KeyCode::Enter | KeyCode::Char('e') if self.showing_diagnostics() => {
+                if let Some(action) = self.open_selected_diagnostic() {
+                    return Ok(action);
+                }
+            }
+            KeyCode::Char('e') => {
+                if let Some(location) = self.selected_location() {
+                    return Ok(Action::OpenEditor(location));
+                }
+            }
+            KeyCode::Char('D') => {
+                self.show_diagnostics = !self.show_diagnostics;
+                self.show_slowest = false;
+                self.selected_diagnostic = 0;
+            }
+            KeyCode::Char('v') => self.toggle_failure_view(),
+            KeyCode::Char('b') => {
+                self.backtrace = self.backtrace.next();
+                return Ok(Action::SetBacktrace(self.backtrace));
+            }
+            KeyCode::Char('B') => {
+                self.expand_backtraces = !self.expand_backtraces;
+                self.refresh_output();
+            }
+            KeyCode::Char('S') => {
+                self.show_slowest = !self.show_slowest;
+                self.show_diagnostics = false;
+                self.selected_test = 0;
+                if self.show_slowest {
+                    self.active_pane = 1;
+                }
+                self.follow_selection();
+            }
+            KeyCode::Char('o') if self.show_slowest => {
+                self.slowest_order = match self.slowest_order {
+                    SlowestOrder::Duration => SlowestOrder::Slowdown,
+                    SlowestOrder::Slowdown => SlowestOrder::Duration,
+                };
+                self.selected_test = 0;
+                self.follow_selection();
+            }
+            KeyCode::Enter => {
+                let specs = self.selected_run_specs();
+                if !specs.is_empty() {
+                    return Ok(Action::Run(specs));
+                }
+            }
+            KeyCode::Char('f') => {
+                if let Some(test_ref) = self.current_test() {
+                    return Ok(Action::Run(vec![self.file_run_spec(test_ref.file)]));
+                }
+            }
+            KeyCode::Char('p') => {
+                if let Some(test_ref) = self.current_test() {
+                    let package = &self.test_info[test_ref.file].package;
+                    return Ok(Action::Run(vec![RunSpec::package(package)]));
+                }
+            }
+            KeyCode::Char('a') if self.filter_matches.is_some() => {
+                let tests: Vec<TestRef> = self
+                    .tree
+                    .as_ref()
+                    .map_or(Vec::new(), |tree| self.node_tests(tree));
+                if !tests.is_empty() {
+                    return Ok(Action::Run(self.exact_run_specs(&tests, "filtered tests")));
+                }
+            }
+            KeyCode::Char('a') if self.workspace.is_some() => {
+                return Ok(Action::Run(vec![self.workspace_run_spec()]));
+            }
+            KeyCode::Char('x') if self.active_pane == 2 => {
+                if let Some(job) = self.jobs.get(self.selected_job) {
+                    return Ok(Action::CancelJob(job.id));
+                }
+            }
+            KeyCode::Char('x') => return Ok(Action::CancelAll),
+            KeyCode::Backspace | KeyCode::Delete if self.active_pane == 2 => {
+                if let Some(job) = self.jobs.get(self.selected_job) {
+                    return Ok(Action::RemoveJob(job.id));
+                }
+            }
+            KeyCode::Char('s') => {
+                if let Some(test_ref) = self.current_test() {
+                    return Ok(Action::Stress(self.test_run_spec(test_ref)));
+                }
+            }
+            KeyCode::Char('F') => {
+                let specs = self.failed_run_specs();
+                if !specs.is_empty() {
+                    return Ok(Action::Run(specs));
+                }
+            }
+            KeyCode::Char(' ') if self.active_pane == 0 && !self.show_history => {
+                self.toggle_expanded()
+            }
+            KeyCode::Char('+') if self.active_pane == 0 && !self.show_history => {
+                self.set_expanded_recursive(true)
+            }
+            KeyCode::Char('-') if self.active_pane == 0 && !self.show_history => {
+                self.set_expanded_recursive(false)
+            }
             //
             KeyCode::Char('h') => self.move_left(),
             KeyCode::Char('l') => self.move_right(),
             KeyCode::Char('j') | KeyCode::Char('k') => self.navigate_list(key),
             _ => {}
         }
-        Ok(false)
+        Ok(Action::None)
+    }
+
+    pub fn set_test_info(&mut self, workspace: Workspace, mut test_info: Vec<TestInfo>) {
+        if let Some(package) = self.package.clone() {
+            if !workspace.packages.iter().any(|p| p.name == package) {
+                self.show_message(&format!("No package named {} in the workspace", package));
+            }
+            test_info.retain(|info| info.package == package);
+        }
+        // Keep the last known outcome of every test that still exists, and
+        // where it moved to.
+        let mut positions = HashMap::new();
+        for (file, info) in test_info.iter().enumerate() {
+            for (index, test) in info.tests.iter().enumerate() {
+                let key = (
+                    info.package.clone(),
+                    info.target.name.clone(),
+                    test.name.clone(),
+                );
+                positions.insert(key, TestRef { file, test: index });
+            }
+        }
+        let mut moved = HashMap::new();
+        for (file, info) in self.test_info.iter().enumerate() {
+            for (index, previous) in info.tests.iter().enumerate() {
+                let key = (
+                    info.package.clone(),
+                    info.target.name.clone(),
+                    previous.name.clone(),
+                );
+                let Some(&test_ref) = positions.get(&key) else {
+                    continue;
+                };
+                let test = &mut test_info[test_ref.file].tests[test_ref.test];
+                test.status = previous.status;
+                test.duration = previous.duration;
+                test.panic = previous.panic.clone();
+                test.output = previous.output.clone();
+                test.stress = previous.stress;
+                moved.insert(TestRef { file, test: index }, test_ref);
+            }
+        }
+        // Running tests stay with their job, which settles them when it
+        // finishes; those that no longer exist are forgotten.
+        self.running = std::mem::take(&mut self.running)
+            .into_iter()
+            .filter_map(|mut running| {
+                running.test = *moved.get(&running.test)?;
+                Some(running)
+            })
+            .collect();
+        for outcomes in self.stress_outcomes.values_mut() {
+            *outcomes = std::mem::take(outcomes)
+                .into_iter()
+                .filter_map(|(test_ref, status, duration)| {
+                    Some((*moved.get(&test_ref)?, status, duration))
+                })
+                .collect();
+        }
+        // Tests are numbered anew, so test output cannot be followed.
+        if let OutputSource::Test(_) | OutputSource::Failure(_) = self.output_source {
+            self.output_source = OutputSource::Log;
+        }
+
+        let name = workspace
+            .root
+            .file_name()
+            .map_or("workspace".to_string(), |name| {
+                name.to_string_lossy().to_string()
+            });
+        let tree = build_tree(&name, &test_info);
+
+        // Start with the workspace and its crates visible; afterwards keep
+        // whatever the user expanded.
+        if self.expanded.is_empty() {
+            self.expanded.insert(tree.id.clone());
+            for package in &tree.children {
+                self.expanded.insert(package.id.clone());
+            }
+        }
+
+        if self.history.is_none() {
+            self.history = Some(History::load(&workspace.target_dir));
+        }
+        self.crate_dirs = workspace
+            .packages
+            .iter()
+            .flat_map(|package| {
+                package
+                    .targets
+                    .iter()
+                    .map(|target| (target.name.replace('-', "_"), package.dir.clone()))
+            })
+            .collect();
+        self.workspace = Some(workspace);
+        self.test_info = test_info;
+        self.tree = Some(tree);
+        self.update_from_history();
+        self.update_filter();
+        self.refresh_output();
+        self.selected_index = self
+            .selected_index
+            .min(self.visible_rows().len().saturating_sub(1));
+        self.selected_test = 0;
+    }
+
+    pub fn visible_rows(&self) -> Vec<TreeRow<'_>> {
+        match &self.tree {
+            Some(tree) => visible_rows(tree, &self.expanded, self.filter_matches.as_ref()),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn selected_node(&self) -> Option<&TreeNode> {
+        self.visible_rows()
+            .get(self.selected_index)
+            .map(|row| row.node)
+    }
+
+    /// The tests shown in the Tests pane: every test below the selected node,
+    /// or the timed tests in the slowest-tests view.
+    pub fn selected_tests(&self) -> Vec<TestRef> {
+        if self.show_slowest {
+            return self.slowest_tests();
+        }
+        self.selected_node()
+            .map(|node| self.node_tests(node))
+            .unwrap_or_default()
+    }
+
+    /// Every test with a duration from its latest run that passes the
+    /// filter, in the chosen order.
+    fn slowest_tests(&self) -> Vec<TestRef> {
+        let mut tests = Vec::new();
+        for (file, info) in self.test_info.iter().enumerate() {
+            for (index, test) in info.tests.iter().enumerate() {
+                let test_ref = TestRef { file, test: index };
+                let matches = self
+                    .filter_matches
+                    .as_ref()
+                    .is_none_or(|matches| matches.contains_key(&test_ref));
+                if test.duration.is_some() && matches {
+                    tests.push(test_ref);
+                }
+            }
+        }
+        match self.slowest_order {
+            SlowestOrder::Duration => {
+                tests.sort_by_key(|test_ref| std::cmp::Reverse(self.test(*test_ref).duration))
+            }
+            SlowestOrder::Slowdown => tests.sort_by(|a, b| {
+                let slowdown = |test_ref: &TestRef| self.test(*test_ref).slowdown().unwrap_or(0.0);
+                slowdown(b).total_cmp(&slowdown(a))
+            }),
+        }
+        tests
+    }
+
+    /// The tests below a node that pass the filter.
+    pub fn node_tests(&self, node: &TreeNode) -> Vec<TestRef> {
+        match &self.filter_matches {
+            Some(matches) => node
+                .tests
+                .iter()
+                .filter(|test| matches.contains_key(test))
+                .copied()
+                .collect(),
+            None => node.tests.clone(),
+        }
+    }
+
+    /// Character positions of a test name that match the filter.
+    pub fn test_matches(&self, test_ref: TestRef) -> &[usize] {
+        self.filter_matches
+            .as_ref()
+            .and_then(|matches| matches.get(&test_ref))
+            .map_or(&[], Vec::as_slice)
+    }
+
+    /// Character positions of a module or test node's label that match the
+    /// filter, taken from the first matching test below it.
+    pub fn label_matches(&self, node: &TreeNode) -> Vec<usize> {
+        if !matches!(node.kind, NodeKind::Module | NodeKind::Test) {
+            return Vec::new();
+        }
+        let Some(test_ref) = self.node_tests(node).first().copied() else {
+            return Vec::new();
+        };
+        // The label is the last segment of the node's path, which is a
+        // prefix of the test name.
+        let offset: usize = node.path[..node.path.len() - 1]
+            .iter()
+            .map(|segment| segment.chars().count() + 2)
+            .sum();
+        let end = offset + node.label.chars().count();
+        self.test_matches(test_ref)
+            .iter()
+            .filter(|index| (offset..end).contains(*index))
+            .map(|index| index - offset)
+            .collect()
+    }
+
+    pub fn set_filter(&mut self, filter: String) {
+        self.filter = filter;
+        self.update_filter();
+        self.selected_index = self
+            .selected_index
+            .min(self.visible_rows().len().saturating_sub(1));
+        self.selected_test = 0;
+    }
+
+    /// Fuzzy matches every test name against the filter.
+    fn update_filter(&mut self) {
+        if self.filter.is_empty() {
+            self.filter_matches = None;
+            return;
+        }
+        let matcher = SkimMatcherV2::default();
+        let mut matches = HashMap::new();
+        for (file, info) in self.test_info.iter().enumerate() {
+            for (index, test) in info.tests.iter().enumerate() {
+                if let Some((_, indices)) = matcher.fuzzy_indices(&test.name, &self.filter) {
+                    matches.insert(TestRef { file, test: index }, indices);
+                }
+            }
+        }
+        self.filter_matches = Some(matches);
+    }
+
+    pub fn test(&self, test_ref: TestRef) -> &TestCase {
+        &self.test_info[test_ref.file].tests[test_ref.test]
+    }
+
+    pub fn test_mut(&mut self, test_ref: TestRef) -> &mut TestCase {
+        &mut self.test_info[test_ref.file].tests[test_ref.test]
+    }
+
+    /// Marks every test a job covers as running.
+    pub fn start_run(&mut self, job: usize, specs: &[RunSpec]) {
+        for (file, info) in self.test_info.iter_mut().enumerate() {
+            for (index, test) in info.tests.iter_mut().enumerate() {
+                let included = specs.iter().any(|spec| {
+                    spec.includes_target(&info.package, &info.target)
+                        && spec.includes_test(&test.name)
+                });
+                // A test already covered by another job stays with that job.
+                if included && test.status != TestStatus::Running {
+                    test.panic = None;
+                    test.output.clear();
+                    // The failure shown is gone; follow the new output.
+                    if self.output_source == OutputSource::Failure(TestRef { file, test: index }) {
+                        self.output_source = OutputSource::Test(TestRef { file, test: index });
+                    }
+                    self.running.push(RunningTest {
+                        job,
+                        test: TestRef { file, test: index },
+                        previous: test.status,
+                    });
+                    test.status = TestStatus::Running;
+                }
+            }
+        }
+        if let OutputSource::Test(_) = self.output_source {
+            self.refresh_output();
+        }
+    }
+
+    /// Tests that the job did not report on keep their previous outcome.
+    pub fn finish_run(&mut self, job: usize) {
+        let (finished, running) = std::mem::take(&mut self.running)
+            .into_iter()
+            .partition(|running| running.job == job);
+        self.running = running;
+        self.binaries.remove(&job);
+        for running in finished {
+            let test = self.test_mut(running.test);
+            if test.status == TestStatus::Running {
+                test.status = running.previous;
+            }
+        }
+    }
+
+    fn record_result(&mut self, job: usize, result: &TestResult) {
+        let Some(test_ref) = self.running_test(job, result.binary.as_deref(), &result.name) else {
+            return;
+        };
+        let test = self.test_mut(test_ref);
+        if test.status == TestStatus::Running {
+            test.status = result.status;
+            test.duration = result.duration;
+        }
+    }
+
+    /// Marks the tests of a killed suite that had not reported as timed out.
+    /// Without the binary name every test still running is marked.
+    fn time_out_suite(&mut self, binary: Option<&str>, ran_for: Duration) -> usize {
+        let mut count = 0;
+        let tests: Vec<TestRef> = self.running.iter().map(|running| running.test).collect();
+        for test_ref in tests {
+            let info = &self.test_info[test_ref.file];
+            if binary.is_some_and(|binary| !same_target(binary, &info.target.name)) {
+                continue;
+            }
+            let test = self.test_mut(test_ref);
+            if test.status == TestStatus::Running {
+                test.status = TestStatus::TimedOut;
+                test.duration = Some(ran_for);
+                count += 1;
+            }
+        }
+        count
+    }
+
+    /// One exact-name run per package and target for every test whose last
+    /// outcome was a failure.
+    pub fn failed_run_specs(&self) -> Vec<RunSpec> {
+        let mut failed = Vec::new();
+        for (file, info) in self.test_info.iter().enumerate() {
+            for (index, test) in info.tests.iter().enumerate() {
+                if matches!(test.status, TestStatus::Failed | TestStatus::TimedOut) {
+                    failed.push(TestRef { file, test: index });
+                }
+            }
+        }
+        self.exact_run_specs(&failed, "failed tests")
+    }
+
+    /// One exact-name run per package and target covering the given tests.
+    fn exact_run_specs(&self, tests: &[TestRef], description: &str) -> Vec<RunSpec> {
+        let mut specs: Vec<RunSpec> = Vec::new();
+        for test_ref in tests {
+            let info = &self.test_info[test_ref.file];
+            let test = &info.tests[test_ref.test];
+            let existing = specs.iter_mut().find(|spec| {
+                spec.package.as_ref() == Some(&info.package)
+                    && spec.target.as_ref() == Some(&info.target)
+            });
+            match existing {
+                Some(spec) => spec.filters.push(test.name.clone()),
+                None => specs.push(RunSpec {
+                    label: format!("{} in {} ({})", description, info.package, info.target.kind),
+                    package: Some(info.package.clone()),
+                    target: Some(info.target.clone()),
+                    filters: vec![test.name.clone()],
+                    exact: true,
+                }),
+            }
+        }
+        specs
+    }
+
+    pub fn test_run_spec(&self, test_ref: TestRef) -> RunSpec {
+        let info = &self.test_info[test_ref.file];
+        let name = self.test(test_ref).name.clone();
+        RunSpec {
+            label: name.clone(),
+            package: Some(info.package.clone()),
+            target: Some(info.target.clone()),
+            filters: vec![name],
+            exact: true,
+        }
+    }
+
+    /// Runs the tests of one file by their exact names.
+    pub fn file_run_spec(&self, file: usize) -> RunSpec {
+        let info = &self.test_info[file];
+        RunSpec {
+            label: self.relative_path(&info.path).display().to_string(),
+            package: Some(info.package.clone()),
+            target: Some(info.target.clone()),
+            filters: info.tests.iter().map(|test| test.name.clone()).collect(),
+            exact: true,
+        }
+    }
+
+    /// The test the cursor is on: the selected test in the Tests pane, or the
+    /// first test under the selected node of the tree.
+    fn current_test(&self) -> Option<TestRef> {
+        let index = if self.active_pane == 1 && !self.show_diagnostics {
+            self.selected_test
+        } else {
+            0
+        };
+        self.selected_tests().get(index).copied()
+    }
+
+    /// Done, total and failed test counts of the jobs in progress.
+    pub fn run_progress(&self) -> Option<(usize, usize, usize)> {
+        if self.running.is_empty() {
+            return None;
+        }
+        let statuses: Vec<TestStatus> = self
+            .running
+            .iter()
+            .map(|running| self.test(running.test).status)
+            .collect();
+        let done = statuses
+            .iter()
+            .filter(|status| **status != TestStatus::Running)
+            .count();
+        let failed = statuses
+            .iter()
+            .filter(|status| matches!(status, TestStatus::Failed | TestStatus::TimedOut))
+            .count();
+        Some((done, statuses.len(), failed))
+    }
+
+    /// What Enter runs. With a filter, a node runs only its matching tests.
+    fn selected_run_specs(&self) -> Vec<RunSpec> {
+        match self.active_pane {
+            0 if self.show_history => Vec::new(),
+            0 => match self.selected_node() {
+                Some(node) if self.filter_matches.is_some() => {
+                    let tests = self.node_tests(node);
+                    if tests.len() == node.tests.len() {
+                        vec![self.node_run_spec(node)]
+                    } else {
+                        self.exact_run_specs(&tests, &format!("matching tests of {}", node.label))
+                    }
+                }
+                Some(node) => vec![self.node_run_spec(node)],
+                None => Vec::new(),
+            },
+            1 => self
+                .selected_tests()
+                .get(self.selected_test)
+                .map(|test_ref| vec![self.test_run_spec(*test_ref)])
+                .unwrap_or_default(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Everything shown: the package picked on the command line, if any, or
+    /// the whole workspace.
+    fn workspace_run_spec(&self) -> RunSpec {
+        match &self.package {
+            Some(package) => RunSpec::package(package),
+            None => RunSpec::workspace(),
+        }
+    }
+
+    fn node_run_spec(&self, node: &TreeNode) -> RunSpec {
+        match node.kind {
+            NodeKind::Workspace => self.workspace_run_spec(),
+            _ => node.run_spec(),
+        }
+    }
+
+    fn toggle_expanded(&mut self) {
+        let Some(node) = self.selected_node() else {
+            return;
+        };
+        if node.is_leaf() {
+            return;
+        }
+        let id = node.id.clone();
+        if !self.expanded.remove(&id) {
+            self.expanded.insert(id);
+        }
+    }
+
+    fn set_expanded_recursive(&mut self, expanded: bool) {
+        let Some(node) = self.selected_node() else {
+            return;
+        };
+        let ids = if node.kind == NodeKind::Workspace && !expanded {
+            // Keep the root open so the crates stay visible.
+            node.ids().into_iter().skip(1).collect()
+        } else {
+            node.ids()
+        };
+        for id in ids {
+            if expanded {
+                self.expanded.insert(id);
+            } else {
+                self.expanded.remove(&id);
+            }
+        }
+    }
+
+    /// Shortens a path to be relative to the workspace root for display.
+    pub fn relative_path<'a>(&self, path: &'a Path) -> &'a Path {
+        self.workspace
+            .as_ref()
+            .and_then(|workspace| path.strip_prefix(&workspace.root).ok())
+            .unwrap_or(path)
     }
 
     pub fn update_output_height(&mut self, height: usize) {
@@ -80,48 +1013,278 @@ impl App {
 
     fn move_left(&mut self) {
         self.active_pane = if self.active_pane == 0 {
-            2
+            3
         } else {
             self.active_pane - 1
         };
+        self.follow_selection();
     }
 
     fn move_right(&mut self) {
-        self.active_pane = (self.active_pane + 1) % 3;
+        self.active_pane = (self.active_pane + 1) % 4;
+        self.follow_selection();
     }
-    pub fn clear_test_output(&mut self) {
-        self.test_output.clear();
-        self.output_scroll = 0;
+    fn showing_diagnostics(&self) -> bool {
+        self.show_diagnostics && self.active_pane == 1
+    }
+
+    fn open_selected_diagnostic(&self) -> Option<Action> {
+        let diagnostic = self.diagnostics.get(self.selected_diagnostic)?;
+        let root = &self.workspace.as_ref()?.root;
+        Some(Action::OpenEditor(Location {
+            path: root.join(diagnostic.path.as_ref()?),
+            line: diagnostic.line,
+            column: diagnostic.column,
+        }))
+    }
+
+    /// What `e` opens: in the output pane, the next workspace file location
+    /// on screen; elsewhere, where the current test last panicked if it
+    /// failed, or else its source.
+    fn selected_location(&mut self) -> Option<Location> {
+        if self.active_pane == 3 {
+            return self.next_output_location();
+        }
+        let root = &self.workspace.as_ref()?.root;
+
+        let test_ref = self.current_test()?;
+        let test = self.test(test_ref);
+        let failed = matches!(test.status, TestStatus::Failed | TestStatus::TimedOut);
+        if let Some(panic) = test.panic.as_ref().filter(|_| failed) {
+            let path = root.join(&panic.path);
+            if path.is_file() {
+                return Some(Location {
+                    path,
+                    ..panic.clone()
+                });
+            }
+        }
+        let (line, column) = test.location?;
+        Some(Location {
+            path: self.test_info[test_ref.file].path.clone(),
+            line,
+            column,
+        })
+    }
+
+    /// The first workspace file location on screen below the one opened
+    /// last, wrapping around to the top of the view.
+    fn next_output_location(&mut self) -> Option<Location> {
+        let root = &self.workspace.as_ref()?.root;
+        let visible = self.output_height.saturating_sub(2).max(1);
+        let locations: Vec<(usize, Location)> = self
+            .output_text()
+            .lines()
+            .enumerate()
+            .skip(self.output_scroll)
+            .take(visible)
+            .filter_map(|(index, line)| {
+                let location = find_location(line)?;
+                let path = root.join(&location.path);
+                path.is_file()
+                    .then_some((index, Location { path, ..location }))
+            })
+            .collect();
+        let (index, location) = locations
+            .iter()
+            .find(|(index, _)| self.opened_line.is_some_and(|opened| *index > opened))
+            .or(locations.first())?
+            .clone();
+        self.opened_line = Some(index);
+        Some(location)
+    }
+
+    /// Remembers where a test of a running job panicked, in the binary
+    /// the output names, if any.
+    fn record_panic(&mut self, job: usize, binary: Option<&str>, line: &str) {
+        let Some((name, location)) = parse_panic(line) else {
+            return;
+        };
+        if let Some(test_ref) = self.running_test(job, binary, &name) {
+            self.test_mut(test_ref).panic = Some(location);
+        }
+    }
+
+    /// A test of a running job by target and name, whether or not it has
+    /// finished. When the output does not name the binary, the one cargo
+    /// last said it was running is used, and failing that any target does.
+    fn running_test(&self, job: usize, binary: Option<&str>, name: &str) -> Option<TestRef> {
+        let binary = binary.or(self.binaries.get(&job).map(String::as_str));
+        self.running
+            .iter()
+            .filter(|running| running.job == job)
+            .map(|running| running.test)
+            .find(|test_ref| {
+                let target = &self.test_info[test_ref.file].target.name;
+                self.test(*test_ref).name == name
+                    && binary.is_none_or(|binary| same_target(binary, target))
+            })
+    }
+
+    pub fn set_jobs(&mut self, jobs: &[Job]) {
+        self.jobs = jobs.to_vec();
+        self.selected_job = self.selected_job.min(self.jobs.len().saturating_sub(1));
+        self.job_output
+            .retain(|id, _| jobs.iter().any(|job| job.id == *id));
+        if let OutputSource::Job(id) = self.output_source {
+            if !self.job_output.contains_key(&id) && !jobs.iter().any(|job| job.id == id) {
+                self.set_output_source(OutputSource::Log);
+            }
+        }
+    }
+
+    /// Searches as the text is typed, moving to the first match from the
+    /// top of the view down.
+    fn set_search(&mut self, search: String) {
+        self.search = search;
+        self.update_search();
+        self.current_match = self
+            .search_matches
+            .iter()
+            .position(|m| m.line >= self.output_scroll)
+            .unwrap_or(0);
+        self.show_current_match();
+    }
+
+    /// Finds every occurrence of the search text, ignoring case unless the
+    /// search has capitals.
+    fn update_search(&mut self) {
+        let mut matches = Vec::new();
+        if self.search.is_empty() {
+            self.search_matches = matches;
+            return;
+        }
+        let ignore_case = !self.search.chars().any(char::is_uppercase);
+        let needle: Vec<char> = if ignore_case {
+            self.search.to_lowercase().chars().collect()
+        } else {
+            self.search.chars().collect()
+        };
+        for (index, line) in self.output_text().lines().enumerate() {
+            let text = plain_text(line);
+            let haystack: Vec<char> = if ignore_case {
+                // Lowercase per character so positions stay aligned.
+                text.chars()
+                    .map(|c| c.to_lowercase().next().unwrap_or(c))
+                    .collect()
+            } else {
+                text.chars().collect()
+            };
+            let mut start = 0;
+            while start + needle.len() <= haystack.len() {
+                if haystack[start..start + needle.len()] == needle[..] {
+                    matches.push(SearchMatch {
+                        line: index,
+                        start,
+                        len: needle.len(),
+                    });
+                    start += needle.len();
+                } else {
+                    start += 1;
+                }
+            }
+        }
+        self.search_matches = matches;
+        self.current_match = self
+            .current_match
+            .min(self.search_matches.len().saturating_sub(1));
+    }
+
+    fn jump_to_match(&mut self, forward: bool) {
+        let count = self.search_matches.len();
+        if count == 0 {
+            return;
+        }
+        self.current_match = if forward {
+            (self.current_match + 1) % count
+        } else {
+            (self.current_match + count - 1) % count
+        };
+        self.show_current_match();
+    }
+
+    fn show_current_match(&mut self) {
+        if let Some(m) = self.search_matches.get(self.current_match) {
+            let visible = self.output_height.saturating_sub(2);
+            if m.line < self.output_scroll || m.line >= self.output_scroll + visible {
+                self.output_scroll = m.line.saturating_sub(visible / 3);
+                self.adjust_scroll();
+            }
+        }
     }
 
     pub fn toggle_watch_mode(&mut self) {
         self.watch_mode = !self.watch_mode;
     }
 
+    pub fn toggle_discovery_mode(&mut self) {
+        self.discovery_mode = match self.discovery_mode {
+            DiscoveryMode::Source => DiscoveryMode::List,
+            DiscoveryMode::List => DiscoveryMode::Source,
+        };
+    }
+
     pub fn navigate_list(&mut self, key: KeyCode) {
+        self.move_selection(key);
+        self.follow_selection();
+    }
+
+    fn move_selection(&mut self, key: KeyCode) {
         match self.active_pane {
+            0 if self.show_history => {
+                let count = self
+                    .history
+                    .as_ref()
+                    .map_or(0, |history| history.runs.len());
+                if key == KeyCode::Char('j') {
+                    self.selected_run = (self.selected_run + 1).min(count.saturating_sub(1));
+                } else if key == KeyCode::Char('k') {
+                    self.selected_run = self.selected_run.saturating_sub(1);
+                }
+            }
+
             0 => {
+                let previous = self.selected_index;
                 if key == KeyCode::Char('j') {
                     self.selected_index =
-                        (self.selected_index + 1).min(self.test_info.len().saturating_sub(1));
+                        (self.selected_index + 1).min(self.visible_rows().len().saturating_sub(1));
                 } else if key == KeyCode::Char('k') {
                     self.selected_index = self.selected_index.saturating_sub(1);
                 }
+                if self.selected_index != previous {
+                    self.selected_test = 0;
+                }
+            }
+
+            1 if self.show_diagnostics => {
+                if key == KeyCode::Char('j') {
+                    self.selected_diagnostic = (self.selected_diagnostic + 1)
+                        .min(self.diagnostics.len().saturating_sub(1));
+                } else if key == KeyCode::Char('k') {
+                    self.selected_diagnostic = self.selected_diagnostic.saturating_sub(1);
+                }
             }
 
             1 => {
-                if let Some(info) = self.test_info.get(self.selected_index) {
-                    if key == KeyCode::Char('j') {
-                        self.selected_test =
-                            (self.selected_test + 1).min(info.tests.len().saturating_sub(1));
-                    } else if key == KeyCode::Char('k') {
-                        self.selected_test = self.selected_test.saturating_sub(1);
-                    }
+                let count = self.selected_tests().len();
+                if key == KeyCode::Char('j') {
+                    self.selected_test = (self.selected_test + 1).min(count.saturating_sub(1));
+                } else if key == KeyCode::Char('k') {
+                    self.selected_test = self.selected_test.saturating_sub(1);
                 }
             }
 
             2 => {
-                let total_lines = self.test_output.lines().count();
+                if key == KeyCode::Char('j') {
+                    self.selected_job =
+                        (self.selected_job + 1).min(self.jobs.len().saturating_sub(1));
+                } else if key == KeyCode::Char('k') {
+                    self.selected_job = self.selected_job.saturating_sub(1);
+                }
+            }
+
+            3 => {
+                let total_lines = self.output_text().lines().count();
                 let page_size = self.output_height.saturating_sub(2); // Subtract 2 for borders
                 match key {
                     KeyCode::Char('j') => self.scroll_down(),
@@ -143,21 +1306,502 @@ impl App {
         }
     }
 
-    pub fn add_test_output(&mut self, new_output: &str) {
-        self.test_output.push_str(new_output);
-        // Limit the total number of lines to prevent excessive memory usage
-        let max_lines = 1000;
-        let lines: Vec<&str> = self.test_output.lines().collect();
-        if lines.len() > max_lines {
-            self.test_output = lines[lines.len() - max_lines..].join("\n");
+    pub fn handle_runner_event(&mut self, event: RunnerEvent) {
+        match event {
+            RunnerEvent::Job { job, event } => self.handle_job_event(job, *event),
+            RunnerEvent::Output(line) => self.append_output(OutputSource::Log, &(line + "\n")),
+            // Everything else only comes from jobs.
+            _ => {}
         }
+    }
+
+    fn handle_job_event(&mut self, job: usize, event: RunnerEvent) {
+        let source = OutputSource::Job(job);
+        match event {
+            // Dropped here rather than with `-Awarnings`, which would change
+            // cargo's fingerprint and rebuild everything.
+            RunnerEvent::Diagnostic(diagnostic)
+                if diagnostic.level == "warning" && !self.show_warnings => {}
+            RunnerEvent::Output(line) => {
+                if let Some(binary) = running_binary(&line) {
+                    self.binaries.insert(job, binary);
+                }
+                self.record_panic(job, None, &line);
+                self.append_output(source, &(line + "\n"));
+            }
+            RunnerEvent::RunStarted {
+                specs,
+                repeat,
+                commands,
+                revision,
+            } => {
+                // Follow the new job unless another one is still running.
+                if self.running.is_empty() {
+                    self.diagnostics.clear();
+                    self.selected_diagnostic = 0;
+                    if !self.showing_history() {
+                        self.set_output_source(source);
+                    }
+                }
+                self.start_run(job, &specs);
+                if repeat > 1 {
+                    self.stress_outcomes.insert(job, Vec::new());
+                    let tests: Vec<TestRef> = self
+                        .running
+                        .iter()
+                        .filter(|running| running.job == job)
+                        .map(|running| running.test)
+                        .collect();
+                    for test_ref in tests {
+                        self.test_mut(test_ref).stress = None;
+                    }
+                }
+                self.start_recording(job, &specs, commands, revision);
+            }
+            RunnerEvent::SuiteStarted { test_count } => {
+                self.append_output(source, &format!("\nrunning {} tests\n", test_count));
+            }
+            RunnerEvent::TestStarted { name } => {
+                debug!("Test started: {}", name);
+            }
+            RunnerEvent::TestFinished(result) => {
+                let output = match &result.line {
+                    Some(line) => format!("{}\n", line),
+                    None => render_result(&result),
+                };
+                for line in result.stdout.iter().flat_map(|stdout| stdout.lines()) {
+                    self.record_panic(job, result.binary.as_deref(), line);
+                }
+                self.append_output(source, &output);
+                if let Some(test_ref) =
+                    self.running_test(job, result.binary.as_deref(), &result.name)
+                {
+                    self.append_output(OutputSource::Test(test_ref), &output);
+                }
+                if self.stress_outcomes.contains_key(&job) {
+                    self.record_stress_result(job, &result);
+                } else {
+                    self.record_result(job, &result);
+                }
+            }
+            RunnerEvent::TestOutput { binary, name, line } => {
+                self.record_panic(job, binary.as_deref(), &line);
+                let line = line + "\n";
+                self.append_output(source, &line);
+                if let Some(test_ref) = self.running_test(job, binary.as_deref(), &name) {
+                    self.append_output(OutputSource::Test(test_ref), &line);
+                }
+            }
+            RunnerEvent::Diagnostic(diagnostic) => {
+                if !diagnostic.rendered.is_empty() {
+                    self.append_output(source, &diagnostic.rendered);
+                }
+                // Summaries such as "aborting due to 2 previous errors" point
+                // at no code and are only shown in the output.
+                if diagnostic.path.is_some() {
+                    self.diagnostics.push(diagnostic);
+                }
+            }
+            RunnerEvent::SuiteTimedOut { binary, ran_for } => {
+                let count = self.time_out_suite(binary.as_deref(), ran_for);
+                self.append_output(
+                    source,
+                    &format!(
+                        "{} test(s) timed out after {}\n",
+                        count,
+                        format_duration(ran_for)
+                    ),
+                );
+            }
+            RunnerEvent::RunFinished {
+                success,
+                description,
+            } => {
+                self.append_output(
+                    source,
+                    &format!("Test finished with status: {}\n", description),
+                );
+                self.report_stress(job);
+                self.save_recording(job, success, &description);
+                self.finish_run(job);
+                debug!("Job {} finished (success: {})", job, success);
+                let errors = self.diagnostics.iter().filter(|d| d.is_error()).count();
+                if errors > 0 {
+                    self.append_output(
+                        source,
+                        &format!(
+                            "Build failed with {} error(s); press D to toggle the list\n",
+                            errors
+                        ),
+                    );
+                    self.show_diagnostics = true;
+                    self.active_pane = 1;
+                }
+            }
+            RunnerEvent::Job { .. } => {}
+        }
+    }
 
-        self.total_output_lines = self.test_output.lines().count();
-        self.scroll_to_bottom();
+    /// The text the output pane shows.
+    pub fn output_text(&self) -> &str {
+        &self.display_text
+    }
+
+    fn source_text(&self) -> &str {
+        match self.output_source {
+            OutputSource::Log => &self.log,
+            OutputSource::Job(job) => self.job_output.get(&job).map_or("", String::as_str),
+            OutputSource::Test(test_ref) => &self.test(test_ref).output,
+            OutputSource::History | OutputSource::Failure(_) => &self.view_text,
+        }
+    }
+
+    fn append_output(&mut self, target: OutputSource, text: &str) {
+        let buffer = match target {
+            OutputSource::Log => &mut self.log,
+            OutputSource::Job(job) => self.job_output.entry(job).or_default(),
+            OutputSource::Test(test_ref) => &mut self.test_mut(test_ref).output,
+            OutputSource::History | OutputSource::Failure(_) => &mut self.view_text,
+        };
+        append_capped(buffer, text);
+        if target != self.output_source {
+            return;
+        }
+
+        self.refresh_text();
+        // Stay on the search results instead of following the output.
+        if self.search.is_empty() {
+            self.scroll_to_bottom();
+        } else {
+            self.update_search();
+        }
+    }
+
+    /// Folds the backtraces of the shown text anew.
+    fn refresh_text(&mut self) {
+        let text = match &self.workspace {
+            Some(workspace) => fold_backtraces(
+                self.source_text(),
+                &self.crate_dirs,
+                &workspace.root,
+                self.expand_backtraces,
+            ),
+            None => self.source_text().to_string(),
+        };
+        self.display_text = text;
+        self.total_output_lines = self.display_text.lines().count();
+    }
+
+    /// Updates the output pane after its text or the way it is shown
+    /// changed.
+    fn refresh_output(&mut self) {
+        self.refresh_text();
+        self.update_search();
+    }
+
+    /// Adds a message to the log and shows it.
+    pub fn show_message(&mut self, message: &str) {
+        self.set_output_source(OutputSource::Log);
+        self.append_output(OutputSource::Log, &format!("{}\n", message));
+    }
+
+    /// Switches what the output pane shows. Runs and the log are followed
+    /// from the bottom, test output is read from the top.
+    fn set_output_source(&mut self, source: OutputSource) {
+        if source == self.output_source {
+            return;
+        }
+        self.output_source = source;
+        self.opened_line = None;
+        self.refresh_output();
+        match source {
+            OutputSource::Test(_) | OutputSource::History | OutputSource::Failure(_) => {
+                self.output_scroll = 0
+            }
+            _ => self.scroll_to_bottom(),
+        }
+    }
+
+    /// Shows the output of what is selected in the Tests or Jobs pane.
+    fn follow_selection(&mut self) {
+        match self.active_pane {
+            0 if self.show_history => self.show_selected_run(),
+            1 if !self.show_diagnostics => {
+                if let Some(test_ref) = self.selected_tests().get(self.selected_test) {
+                    self.set_output_source(OutputSource::Test(*test_ref));
+                }
+            }
+            2 => {
+                if let Some(job) = self.jobs.get(self.selected_job) {
+                    self.set_output_source(OutputSource::Job(job.id));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn showing_history(&self) -> bool {
+        self.show_history && self.active_pane == 0
+    }
+
+    fn toggle_history(&mut self) {
+        self.show_history = !self.show_history;
+        if self.show_history {
+            self.active_pane = 0;
+            self.selected_run = 0;
+            self.show_selected_run();
+        } else if self.output_source == OutputSource::History {
+            self.set_output_source(OutputSource::Log);
+        }
+    }
+
+    /// The run selected in the history view.
+    pub fn selected_run(&self) -> Option<&RunRecord> {
+        let runs = &self.history.as_ref()?.runs;
+        runs.iter().rev().nth(self.selected_run)
+    }
+
+    /// Marks the selected run to compare the others against, or clears the
+    /// mark when it is already on the selected run.
+    fn toggle_compare_base(&mut self) {
+        let Some(id) = self.selected_run().map(|run| run.id.clone()) else {
+            return;
+        };
+        self.compare_base = if self.compare_base.as_ref() == Some(&id) {
+            None
+        } else {
+            Some(id)
+        };
+        self.show_selected_run();
+    }
+
+    /// Switches the output pane between the output of the current test and
+    /// why it failed.
+    fn toggle_failure_view(&mut self) {
+        if let OutputSource::Failure(test_ref) = self.output_source {
+            self.set_output_source(OutputSource::Test(test_ref));
+            return;
+        }
+        let Some(test_ref) = self.current_test() else {
+            return;
+        };
+        let test = self.test(test_ref);
+        if !matches!(test.status, TestStatus::Failed | TestStatus::TimedOut) {
+            return;
+        }
+        let text = match parse_failure(&test.output) {
+            Some(failure) => render(&failure),
+            None => format!("No panic found in the output of {}\n", test.name),
+        };
+        self.show_view(OutputSource::Failure(test_ref), text);
+    }
+
+    /// Shows generated text in the output pane, from the top.
+    fn show_view(&mut self, source: OutputSource, text: String) {
+        self.view_text = text;
+        self.output_source = source;
+        self.output_scroll = 0;
+        self.refresh_output();
+    }
+
+    /// Shows the selected run in the output pane, or what changed since the
+    /// marked run when another run is selected.
+    fn show_selected_run(&mut self) {
+        let text = match self.selected_run() {
+            Some(run) => {
+                let base = self.history.as_ref().and_then(|history| {
+                    let id = self.compare_base.as_ref().filter(|id| **id != run.id)?;
+                    history.runs.iter().find(|base| base.id == *id)
+                });
+                match base {
+                    Some(base) => run.compare(base),
+                    None => run.render(),
+                }
+            }
+            None => "No runs recorded yet\n".to_string(),
+        };
+        self.show_view(OutputSource::History, text);
+    }
+
+    fn start_recording(
+        &mut self,
+        job: usize,
+        specs: &[RunSpec],
+        commands: Vec<String>,
+        revision: Option<Revision>,
+    ) {
+        let label = match self.jobs.iter().find(|j| j.id == job) {
+            Some(job) => job.label.clone(),
+            None => specs
+                .iter()
+                .map(|spec| spec.label.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+        };
+        self.recording.insert(
+            job,
+            RunRecord {
+                id: job.to_string(),
+                started: Local::now(),
+                duration: Duration::ZERO,
+                label,
+                commands,
+                revision,
+                success: false,
+                description: String::new(),
+                tests: Vec::new(),
+                output: String::new(),
+            },
+        );
+    }
+
+    /// Saves a finished job with the outcome of every test it reported on.
+    /// Must run after `report_stress` and before `finish_run` forgets which
+    /// tests the job covered.
+    fn save_recording(&mut self, job: usize, success: bool, description: &str) {
+        let Some(mut run) = self.recording.remove(&job) else {
+            return;
+        };
+        run.duration = (Local::now() - run.started).to_std().unwrap_or_default();
+        run.success = success;
+        run.description = description.to_string();
+        run.output = self.job_output.get(&job).cloned().unwrap_or_default();
+        // `report_stress` has settled the stressed tests, so a stress run
+        // counts once in the flakiness scores and baselines.
+        for running in self.running.iter().filter(|running| running.job == job) {
+            let info = &self.test_info[running.test.file];
+            let test = &info.tests[running.test.test];
+            if test.status == TestStatus::Running {
+                continue;
+            }
+            run.tests.push(TestRecord {
+                package: info.package.clone(),
+                target: info.target.name.clone(),
+                name: test.name.clone(),
+                status: test.status,
+                duration: test.duration,
+                output: test.output.clone(),
+            });
+        }
+
+        let Some(history) = self.history.as_mut() else {
+            return;
+        };
+        if let Err(err) = history.save(run) {
+            self.append_output(
+                OutputSource::Log,
+                &format!("Failed to save the run to the history: {}\n", err),
+            );
+            return;
+        }
+        if self.show_history {
+            // The new run is listed on top: follow it from the top of the
+            // list, otherwise stay on the run that was selected.
+            if self.selected_run > 0 {
+                self.selected_run += 1;
+            }
+            let count = self
+                .history
+                .as_ref()
+                .map_or(0, |history| history.runs.len());
+            self.selected_run = self.selected_run.min(count.saturating_sub(1));
+            if self.output_source == OutputSource::History {
+                self.show_selected_run();
+            }
+        }
+        self.update_from_history();
+    }
+
+    /// Looks up the flakiness score and duration baseline of every test in
+    /// the history.
+    fn update_from_history(&mut self) {
+        let scores = self.history.as_ref().map(flakiness).unwrap_or_default();
+        let baselines = self.history.as_ref().map(baselines).unwrap_or_default();
+        for info in &mut self.test_info {
+            for test in &mut info.tests {
+                let key = (
+                    info.package.clone(),
+                    info.target.name.clone(),
+                    test.name.clone(),
+                );
+                test.flakiness = scores.get(&key).copied();
+                test.baseline = baselines.get(&key).copied();
+            }
+        }
+    }
+
+    /// Counts one repetition of a stress run. The test stays running until
+    /// the last one.
+    fn record_stress_result(&mut self, job: usize, result: &TestResult) {
+        let Some(test_ref) = self.running_test(job, result.binary.as_deref(), &result.name) else {
+            return;
+        };
+        let Some(outcomes) = self.stress_outcomes.get_mut(&job) else {
+            return;
+        };
+        outcomes.push((test_ref, result.status, result.duration));
+        let passed = outcomes
+            .iter()
+            .filter(|(t, status, _)| *t == test_ref && *status == TestStatus::Passed)
+            .count();
+        let total = outcomes.iter().filter(|(t, _, _)| *t == test_ref).count();
+        let test = self.test_mut(test_ref);
+        test.stress = Some((passed, total));
+        test.duration = result.duration;
+    }
+
+    /// Settles the outcome of the stressed tests, timed out or failed if any
+    /// repetition was, and their mean duration, and reports their pass rates.
+    fn report_stress(&mut self, job: usize) {
+        let Some(outcomes) = self.stress_outcomes.remove(&job) else {
+            return;
+        };
+        let mut tests: Vec<TestRef> = Vec::new();
+        for (test_ref, _, _) in &outcomes {
+            if !tests.contains(test_ref) {
+                tests.push(*test_ref);
+            }
+        }
+        let mut report = String::new();
+        for test_ref in tests {
+            let repetitions: Vec<(TestStatus, Option<Duration>)> = outcomes
+                .iter()
+                .filter(|(t, _, _)| *t == test_ref)
+                .map(|(_, status, duration)| (*status, *duration))
+                .collect();
+            let statuses: Vec<TestStatus> = repetitions.iter().map(|(status, _)| *status).collect();
+            let durations: Vec<Duration> = repetitions
+                .iter()
+                .filter_map(|(_, duration)| *duration)
+                .collect();
+            let passed = statuses
+                .iter()
+                .filter(|status| **status == TestStatus::Passed)
+                .count();
+            let test = self.test_mut(test_ref);
+            if statuses.contains(&TestStatus::TimedOut) {
+                test.status = TestStatus::TimedOut;
+            } else if statuses.contains(&TestStatus::Failed) {
+                test.status = TestStatus::Failed;
+            } else if let Some(status) = statuses.last() {
+                test.status = *status;
+            }
+            if !durations.is_empty() {
+                test.duration = Some(durations.iter().sum::<Duration>() / durations.len() as u32);
+            }
+            report.push_str(&format!(
+                "Stress: {} passed {} of {} runs ({:.0}%)\n",
+                test.name,
+                passed,
+                statuses.len(),
+                passed as f64 * 100.0 / statuses.len() as f64
+            ));
+        }
+        self.append_output(OutputSource::Job(job), &report);
     }
 
     pub fn update_scroll(&mut self) {
-        let total_lines = self.test_output.lines().count();
+        let total_lines = self.output_text().lines().count();
         if total_lines > self.output_height {
             self.output_scroll = self.output_scroll.min(total_lines - 1);
         } else {
@@ -165,3 +1809,48 @@ impl App {
         }
     }
 }
+
+/// Appends to an output buffer, keeping only its last lines to bound memory.
+fn append_capped(buffer: &mut String, text: &str) {
+    const MAX_LINES: usize = 1000;
+    buffer.push_str(text);
+    let lines = buffer.lines().count();
+    if lines > MAX_LINES {
+        let start: usize = buffer
+            .split_inclusive('\n')
+            .take(lines - MAX_LINES)
+            .map(str::len)
+            .sum();
+        buffer.drain(..start);
+    }
+}
+
+/// A line of output without its escape sequences.
+pub fn plain_text(line: &str) -> String {
+    line.ansi_parse()
+        .filter_map(|output| match output {
+            Output::TextBlock(text) => Some(text),
+            Output::Escape(_) => None,
+        })
+        .collect()
+}
+
+/// Formats a structured result the way libtest prints it with `--report-time`.
+pub fn render_result(result: &TestResult) -> String {
+    let outcome = match result.status {
+        TestStatus::Passed => "ok",
+        TestStatus::Failed => "FAILED",
+        TestStatus::Ignored => "ignored",
+        TestStatus::TimedOut => "timed out",
+        TestStatus::Pending | TestStatus::Running => "unknown",
+    };
+    let mut output = format!("test {} ... {}", result.name, outcome);
+    if let Some(duration) = result.duration {
+        output.push_str(&format!(" <{:.3}s>", duration.as_secs_f64()));
+    }
+    output.push('\n');
+    if let Some(stdout) = result.stdout.as_deref().filter(|stdout| !stdout.is_empty()) {
+        output.push_str(&format!("---- {} stdout ----\n{}\n", result.name, stdout));
+    }
+    output
+}
@@ -0,0 +1,276 @@
+use crate::events::strip_ansi;
+use regex::Regex;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Crates whose frames are folded away: the standard library, the panic
+/// machinery, the test harness and the tokio runtime. `fn` stands for the
+/// function pointer shims the harness calls tests through.
+const HIDDEN_CRATES: &[&str] = &[
+    "std",
+    "core",
+    "alloc",
+    "test",
+    "tokio",
+    "__rustc",
+    "rust_begin_unwind",
+    "panic_unwind",
+    "fn",
+    "unknown",
+];
+
+const WORKSPACE_FRAME: &str = "\x1b[1;33m";
+const WORKSPACE_LOCATION: &str = "\x1b[36m";
+const FOLDED: &str = "\x1b[90m";
+const RESET: &str = "\x1b[0m";
+
+/// A frame of a backtrace: its numbered symbol line and the source
+/// locations under it.
+struct Frame<'a> {
+    symbol_line: &'a str,
+    crate_name: String,
+    locations: Vec<(&'a str, String)>,
+}
+
+/// Rewrites the backtraces in some output for the output pane: frames of
+/// the workspace's crates stand out and have their locations made relative
+/// to the workspace root, and unless `expand` is set, runs of std, core,
+/// tokio and test harness frames fold into a single line.
+///
+/// `crate_dirs` maps the crate names of the workspace, as they appear in
+/// symbols, to the package directory that frame locations are relative to.
+pub fn fold_backtraces(
+    text: &str,
+    crate_dirs: &HashMap<String, PathBuf>,
+    root: &Path,
+    expand: bool,
+) -> String {
+    static FRAME: OnceLock<Regex> = OnceLock::new();
+    static LOCATION: OnceLock<Regex> = OnceLock::new();
+    let frame_re =
+        FRAME.get_or_init(|| Regex::new(r"^\s*\d+:\s+(?:0x[0-9a-fA-F]+ - )?(.+?)\s*$").unwrap());
+    let location_re = LOCATION.get_or_init(|| Regex::new(r"^\s+at (.+)$").unwrap());
+
+    let mut out = String::with_capacity(text.len());
+    let mut frames: Vec<Frame> = Vec::new();
+    let mut in_backtrace = false;
+    for line in text.lines() {
+        let plain = strip_ansi(line);
+        if in_backtrace {
+            if let Some(caps) = frame_re.captures(&plain) {
+                frames.push(Frame {
+                    symbol_line: line,
+                    crate_name: frame_crate(&caps[1]).to_string(),
+                    locations: Vec::new(),
+                });
+                continue;
+            }
+            if let (Some(caps), Some(frame)) = (location_re.captures(&plain), frames.last_mut()) {
+                frame.locations.push((line, caps[1].to_string()));
+                continue;
+            }
+            push_frames(&mut out, &frames, crate_dirs, root, expand);
+            frames.clear();
+            in_backtrace = false;
+        }
+        if plain.trim() == "stack backtrace:" {
+            in_backtrace = true;
+        }
+        out.push_str(line);
+        out.push('\n');
+    }
+    push_frames(&mut out, &frames, crate_dirs, root, expand);
+    out
+}
+
+fn push_frames(
+    out: &mut String,
+    frames: &[Frame],
+    crate_dirs: &HashMap<String, PathBuf>,
+    root: &Path,
+    expand: bool,
+) {
+    let mut hidden: Vec<&str> = Vec::new();
+    let flush = |out: &mut String, hidden: &mut Vec<&str>| {
+        if hidden.is_empty() {
+            return;
+        }
+        let count = hidden.len();
+        hidden.sort();
+        hidden.dedup();
+        out.push_str(&format!(
+            "{}      ... {} frame{} hidden ({}){}\n",
+            FOLDED,
+            count,
+            if count == 1 { "" } else { "s" },
+            hidden.join(", "),
+            RESET
+        ));
+        hidden.clear();
+    };
+
+    for frame in frames {
+        if !expand && HIDDEN_CRATES.contains(&frame.crate_name.as_str()) {
+            hidden.push(frame.crate_name.as_str());
+            continue;
+        }
+        flush(out, &mut hidden);
+
+        let Some(dir) = crate_dirs.get(&frame.crate_name) else {
+            out.push_str(frame.symbol_line);
+            out.push('\n');
+            for (line, _) in &frame.locations {
+                out.push_str(line);
+                out.push('\n');
+            }
+            continue;
+        };
+        out.push_str(&format!(
+            "{}{}{}\n",
+            WORKSPACE_FRAME, frame.symbol_line, RESET
+        ));
+        for (line, location) in &frame.locations {
+            let indent = &line[..line.len() - line.trim_start().len()];
+            out.push_str(&format!(
+                "{}{}at {}{}\n",
+                indent,
+                WORKSPACE_LOCATION,
+                workspace_path(location, dir, root),
+                RESET
+            ));
+        }
+    }
+    flush(out, &mut hidden);
+}
+
+/// A frame location relative to the workspace root. Locations in the
+/// package the test runs in are printed relative to the package directory.
+fn workspace_path(location: &str, dir: &Path, root: &Path) -> String {
+    let path = Path::new(location);
+    let path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        dir.join(location.strip_prefix("./").unwrap_or(location))
+    };
+    path.strip_prefix(root)
+        .map_or(path.as_path(), |relative| relative)
+        .display()
+        .to_string()
+}
+
+/// The crate a symbol belongs to, e.g. `core` for
+/// `core::panicking::panic_fmt` or `my_crate` for `<my_crate::Foo as Trait>::f`.
+fn frame_crate(symbol: &str) -> &str {
+    let symbol = symbol.trim_start_matches(['<', '&']);
+    let symbol = symbol.strip_prefix("mut ").unwrap_or(symbol);
+    let symbol = symbol.strip_prefix("dyn ").unwrap_or(symbol);
+    symbol
+        .split([':', '<', '>', ' ', '('])
+        .next()
+        .unwrap_or(symbol)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BACKTRACE: &str = "\
+thread 'tests::std_ne' (28680) panicked at crates/asserts/src/lib.rs:18:9:
+assertion `left != right` failed
+stack backtrace:
+   0: __rustc::rust_begin_unwind
+             at /rustc/1159e78c4747b02ef996e55082b704c09b970588/library/std/src/panicking.rs:697:5
+   1: core::panicking::panic_fmt
+             at /rustc/1159e78c4747b02ef996e55082b704c09b970588/library/core/src/panicking.rs:75:14
+   2: core::panicking::assert_failed_inner
+   3: core::panicking::assert_failed
+             at /rustc/1159e78c4747b02ef996e55082b704c09b970588/library/core/src/panicking.rs:380:5
+   4: asserts::tests::std_ne
+             at ./src/lib.rs:18:9
+   5: asserts::tests::std_ne::{{closure}}
+             at ./src/lib.rs:16:16
+   6: core::ops::function::FnOnce::call_once
+             at /rustc/1159e78c4747b02ef996e55082b704c09b970588/library/core/src/ops/function.rs:250:5
+note: Some details are omitted, run with `RUST_BACKTRACE=full` for a verbose backtrace.
+";
+
+    fn fold(text: &str, expand: bool) -> String {
+        let root = Path::new("/ws");
+        let crate_dirs = HashMap::from([("asserts".to_string(), root.join("crates/asserts"))]);
+        strip_ansi(&fold_backtraces(text, &crate_dirs, root, expand)).into_owned()
+    }
+
+    #[test]
+    fn folds_library_frames() {
+        assert_eq!(
+            fold(BACKTRACE, false),
+            "\
+thread 'tests::std_ne' (28680) panicked at crates/asserts/src/lib.rs:18:9:
+assertion `left != right` failed
+stack backtrace:
+      ... 4 frames hidden (__rustc, core)
+   4: asserts::tests::std_ne
+             at crates/asserts/src/lib.rs:18:9
+   5: asserts::tests::std_ne::{{closure}}
+             at crates/asserts/src/lib.rs:16:16
+      ... 1 frame hidden (core)
+note: Some details are omitted, run with `RUST_BACKTRACE=full` for a verbose backtrace.
+"
+        );
+    }
+
+    #[test]
+    fn expanded_keeps_every_frame() {
+        let expanded = fold(BACKTRACE, true);
+        assert!(!expanded.contains("hidden"));
+        assert!(expanded.contains("   2: core::panicking::assert_failed_inner\n"));
+        assert!(expanded.contains("             at crates/asserts/src/lib.rs:18:9\n"));
+        assert_eq!(expanded.lines().count(), BACKTRACE.lines().count());
+    }
+
+    #[test]
+    fn highlights_workspace_frames() {
+        let root = Path::new("/ws");
+        let crate_dirs = HashMap::from([("asserts".to_string(), root.join("crates/asserts"))]);
+        let folded = fold_backtraces(BACKTRACE, &crate_dirs, root, false);
+        assert!(folded.contains("\x1b[1;33m   4: asserts::tests::std_ne\x1b[0m\n"));
+        assert!(folded.contains("             \x1b[36mat crates/asserts/src/lib.rs:18:9\x1b[0m\n"));
+    }
+
+    #[test]
+    fn full_backtraces() {
+        let text = "\
+stack backtrace:
+   0:     0x55d1c2a3b4c5 - std::backtrace_rs::backtrace::libunwind::trace::h0123456789abcdef
+   1:     0x55d1c2a3b4c6 - asserts::tests::std_ne::h0123456789abcdef
+                               at /ws/crates/asserts/src/lib.rs:18:9
+";
+        assert_eq!(
+            fold(text, false),
+            "\
+stack backtrace:
+      ... 1 frame hidden (std)
+   1:     0x55d1c2a3b4c6 - asserts::tests::std_ne::h0123456789abcdef
+                               at crates/asserts/src/lib.rs:18:9
+"
+        );
+    }
+
+    #[test]
+    fn leaves_other_output_alone() {
+        let text = "running 1 test\n   1: not a frame\ntest tests::adds ... ok\n";
+        assert_eq!(fold(text, false), text);
+    }
+
+    #[test]
+    fn frame_crates() {
+        assert_eq!(frame_crate("core::panicking::panic_fmt"), "core");
+        assert_eq!(
+            frame_crate("<my_crate::Foo as core::ops::Drop>::drop"),
+            "my_crate"
+        );
+        assert_eq!(frame_crate("<&mut dyn my_crate::Trait>::call"), "my_crate");
+        assert_eq!(frame_crate("rust_begin_unwind"), "rust_begin_unwind");
+    }
+}
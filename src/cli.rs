@@ -0,0 +1,205 @@
+use crate::app::render_result;
+use crate::config::{Config, Runner};
+use crate::events::{strip_ansi, RunnerEvent};
+use crate::test_runner::{JobState, RunSpec, TestRunner};
+use crate::utils::scan_for_tests;
+use crate::workspace::Workspace;
+use clap::{Parser, Subcommand, ValueEnum};
+use std::error::Error;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread;
+use std::time::Duration;
+
+/// A terminal UI to find, run and watch the tests of a Rust workspace.
+#[derive(Parser)]
+#[command(version)]
+pub struct Cli {
+    /// The project to test.
+    #[arg(global = true, default_value = ".")]
+    pub path: PathBuf,
+    /// Only show and run the tests of this package.
+    #[arg(short, long, global = true)]
+    pub package: Option<String>,
+    /// Features to build with, separated by commas or spaces.
+    #[arg(short = 'F', long, global = true, value_delimiter = ',')]
+    pub features: Vec<String>,
+    /// Build with every feature of the selected packages.
+    #[arg(long, global = true)]
+    pub all_features: bool,
+    /// What runs the tests.
+    #[arg(long, global = true)]
+    pub runner: Option<RunnerArg>,
+    /// Only tests whose name contains this text; in the UI, the initial
+    /// filter.
+    #[arg(long, global = true)]
+    pub filter: Option<String>,
+    /// Start in watch mode.
+    #[arg(short, long, global = true)]
+    pub watch: bool,
+    /// Read the settings from this file instead of `lazytestr.toml`.
+    #[arg(short, long, global = true)]
+    pub config: Option<PathBuf>,
+    /// Where to write the debug log.
+    #[arg(long, global = true, default_value = "debug.log")]
+    pub log_file: PathBuf,
+    #[command(subcommand)]
+    pub command: Option<CliCommand>,
+}
+
+#[derive(Subcommand)]
+pub enum CliCommand {
+    /// Print the tests found in the source, one per line.
+    List,
+    /// Run the tests, print their output and exit with their status.
+    Run,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum RunnerArg {
+    Cargo,
+    Nextest,
+}
+
+impl Cli {
+    /// Lets the options override the config file.
+    pub fn apply(&self, config: &mut Config) {
+        if self.all_features {
+            config.all_features = true;
+        }
+        if !self.features.is_empty() {
+            config.features = self
+                .features
+                .iter()
+                .flat_map(|features| features.split_whitespace())
+                .map(str::to_string)
+                .collect();
+        }
+        match self.runner {
+            Some(RunnerArg::Cargo) => config.runner = Runner::Cargo,
+            Some(RunnerArg::Nextest) => config.runner = Runner::Nextest,
+            None => {}
+        }
+    }
+
+    /// What `run` runs: the selected package or the whole workspace.
+    fn run_spec(&self) -> RunSpec {
+        let mut spec = match &self.package {
+            Some(package) => RunSpec::package(package),
+            None => RunSpec::workspace(),
+        };
+        spec.filters.extend(self.filter.iter().cloned());
+        spec
+    }
+}
+
+/// Prints every test as `package target name`.
+pub fn list(cli: &Cli) -> Result<(), Box<dyn Error>> {
+    let workspace = Workspace::load(&cli.path)?;
+    let mut tests = Vec::new();
+    for info in scan_for_tests(&workspace)? {
+        if cli
+            .package
+            .as_ref()
+            .is_some_and(|package| *package != info.package)
+        {
+            continue;
+        }
+        for test in &info.tests {
+            if cli
+                .filter
+                .as_ref()
+                .is_none_or(|filter| test.name.contains(filter.as_str()))
+            {
+                tests.push(format!(
+                    "{} {} {}",
+                    info.package, info.target.name, test.name
+                ));
+            }
+        }
+    }
+    tests.sort();
+    let mut stdout = io::stdout().lock();
+    for test in tests {
+        match writeln!(stdout, "{}", test) {
+            // The reader has seen enough, as with `lazytestr list | head`.
+            Err(err) if err.kind() == io::ErrorKind::BrokenPipe => break,
+            result => result?,
+        }
+    }
+    Ok(())
+}
+
+/// Runs the tests without the UI and returns whether they passed.
+pub fn run(cli: &Cli, config: &Config) -> Result<bool, Box<dyn Error>> {
+    let workspace = Workspace::load(&cli.path)?;
+    let (output_tx, output_rx) = channel();
+    let (scan_tx, _scan_rx) = channel();
+    let mut test_runner = TestRunner::new(output_tx, scan_tx, config);
+    test_runner.enqueue(&workspace.root, &[cli.run_spec()]);
+
+    loop {
+        test_runner.update();
+        print_events(&output_rx, config);
+        if let Some(job) = test_runner.jobs().first().filter(|job| job.is_done()) {
+            // The job's last events are sent before it counts as done.
+            print_events(&output_rx, config);
+            return Ok(job.state == JobState::Passed);
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// Prints the output of a run the way the output pane shows it. cargo is
+/// asked for colors, which are dropped unless stdout is a terminal.
+fn print_events(output_rx: &Receiver<RunnerEvent>, config: &Config) {
+    let color = io::stdout().is_terminal();
+    let text = |text: &str| {
+        if color {
+            text.to_string()
+        } else {
+            strip_ansi(text).into_owned()
+        }
+    };
+    while let Ok(event) = output_rx.try_recv() {
+        let event = match event {
+            RunnerEvent::Job { event, .. } => *event,
+            event => event,
+        };
+        match event {
+            RunnerEvent::Output(line) | RunnerEvent::TestOutput { line, .. } => {
+                println!("{}", text(&line))
+            }
+            RunnerEvent::SuiteStarted { test_count } => {
+                println!("\nrunning {} tests", test_count)
+            }
+            RunnerEvent::TestFinished(result) => match &result.line {
+                Some(line) => println!("{}", text(line)),
+                None => print!("{}", text(&render_result(&result))),
+            },
+            RunnerEvent::Diagnostic(diagnostic)
+                if diagnostic.level != "warning" || config.show_warnings =>
+            {
+                print!("{}", text(&diagnostic.rendered))
+            }
+            RunnerEvent::SuiteTimedOut { binary, ran_for } => println!(
+                "{} timed out after {:.1}s",
+                binary.as_deref().unwrap_or("suite"),
+                ran_for.as_secs_f64()
+            ),
+            RunnerEvent::RunFinished { description, .. } => {
+                println!("Test finished with status: {}", description)
+            }
+            _ => {}
+        }
+    }
+}
+
+/// The log file as a watch ignore pattern, when it is inside the project.
+pub fn log_file_pattern(root: &Path, log_file: &Path) -> Option<String> {
+    let root = root.canonicalize().ok()?;
+    let log_file = log_file.canonicalize().ok()?;
+    let relative = log_file.strip_prefix(root).ok()?;
+    Some(format!("/{}", relative.display()))
+}
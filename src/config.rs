@@ -1,18 +1,834 @@
-use simplelog::{Config, LevelFilter, WriteLogger};
+use crossterm::event::KeyCode;
+use ignore::gitignore::GitignoreBuilder;
+use log::info;
+use simplelog::{Config as LogConfig, LevelFilter, WriteLogger};
+use std::collections::HashMap;
 use std::env;
 use std::error::Error;
-use std::fs::File;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use toml::{Table, Value};
+use tui::style::Color;
 
-pub fn setup_environment() -> Result<(), Box<dyn Error>> {
-    env::set_var("CARGO_INCREMENTAL", "0");
-    env::set_var("RUSTFLAGS", "-Awarnings");
-    env::set_var("CARGO_TERM_COLOR", "always");
+const FILE_NAME: &str = "lazytestr.toml";
+
+/// Keys that can be rebound in the `[keys]` table, by action name, with the
+/// panes they work in.
+const ACTIONS: &[(&str, char, Panes)] = &[
+    ("quit", 'q', Panes::All),
+    ("watch", 'w', Panes::All),
+    ("discovery-mode", 'm', Panes::All),
+    ("rescan", 'r', Panes::All),
+    ("run-file", 'f', Panes::All),
+    ("run-crate", 'p', Panes::All),
+    ("run-all", 'a', Panes::All),
+    ("rerun-failed", 'F', Panes::All),
+    ("stress", 's', Panes::All),
+    ("cancel", 'x', Panes::All),
+    ("open-editor", 'e', Panes::All),
+    ("diagnostics", 'D', Panes::All),
+    ("history", 'H', Panes::All),
+    ("compare", 'c', Panes::All),
+    ("slowest", 'S', Panes::All),
+    ("sort", 'o', Panes::All),
+    ("failure-view", 'v', Panes::All),
+    ("backtrace", 'b', Panes::All),
+    ("expand-backtraces", 'B', Panes::All),
+    ("filter", '/', Panes::Lists),
+    ("search", '/', Panes::Output),
+    ("next-match", 'n', Panes::Output),
+    ("previous-match", 'N', Panes::Output),
+    ("page-down", 'd', Panes::Output),
+    ("page-up", 'u', Panes::Output),
+    ("down", 'j', Panes::All),
+    ("up", 'k', Panes::All),
+    ("left", 'h', Panes::All),
+    ("right", 'l', Panes::All),
+    ("toggle-node", ' ', Panes::All),
+    ("expand-all", '+', Panes::All),
+    ("collapse-all", '-', Panes::All),
+];
+
+/// Where the key of an action works, so that actions of different panes
+/// can share a key.
+#[derive(Clone, Copy, PartialEq)]
+enum Panes {
+    All,
+    /// Every pane but the output pane.
+    Lists,
+    Output,
+}
+
+impl Panes {
+    fn includes(self, output: bool) -> bool {
+        match self {
+            Panes::All => true,
+            Panes::Lists => !output,
+            Panes::Output => output,
+        }
+    }
+
+    fn overlaps(self, other: Panes) -> bool {
+        self == other || self == Panes::All || other == Panes::All
+    }
+}
+
+/// Which tool runs the tests.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Runner {
+    /// nextest when it is installed, otherwise `cargo test`.
+    Auto,
+    Cargo,
+    Nextest,
+}
+
+/// Settings from `lazytestr.toml` in the project root and in the user's
+/// config directory, with the project's taking precedence, and from the
+/// `LAZYTESTR_*` environment variables, which override both.
+pub struct Config {
+    pub runner: Runner,
+    /// Passed to cargo for every build and run, e.g. `--release`.
+    pub cargo_args: Vec<String>,
+    pub features: Vec<String>,
+    pub all_features: bool,
+    /// Set for lazytestr and everything it runs.
+    pub env: Vec<(String, String)>,
+    /// Ask the harness for structured JSON results; see `TestRunner`.
+    pub libtest_json: bool,
+    /// Whether compiler warnings are shown along with errors.
+    pub show_warnings: bool,
+    /// A target directory of lazytestr's own, so that its builds do not
+    /// take turns with the user's for the same artifacts.
+    pub target_dir: Option<PathBuf>,
+    /// How many jobs may run at once.
+    pub jobs: usize,
+    /// How many times a stress run repeats a test.
+    pub stress_runs: usize,
+    pub test_timeout: Option<Duration>,
+    pub run_timeout: Option<Duration>,
+    /// Editor command template with `{file}`, `{line}` and `{column}`.
+    pub editor: Option<String>,
+    /// What watch mode watches, relative to the project root.
+    pub watch_paths: Vec<PathBuf>,
+    /// Gitignore-style patterns of changes that watch mode skips.
+    pub watch_ignore: Vec<String>,
+    pub keymap: Keymap,
+    /// The keys of the `[keys]` tables by action, for `keymap`.
+    key_bindings: HashMap<String, char>,
+    pub theme: Theme,
+    /// The files the settings were read from.
+    pub files: Vec<PathBuf>,
+    /// Problems found in the files and the environment, for the UI.
+    pub errors: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            runner: Runner::Auto,
+            cargo_args: Vec::new(),
+            features: Vec::new(),
+            all_features: false,
+            env: Vec::new(),
+            libtest_json: false,
+            show_warnings: false,
+            target_dir: None,
+            jobs: 1,
+            stress_runs: 20,
+            test_timeout: None,
+            run_timeout: None,
+            editor: None,
+            watch_paths: vec![PathBuf::from(".")],
+            watch_ignore: vec!["target/".to_string(), ".git/".to_string()],
+            keymap: Keymap::default(),
+            key_bindings: HashMap::new(),
+            theme: Theme::default(),
+            files: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the settings for the project in `root`, from `file` instead of
+    /// the usual places when it is given. Invalid settings are reported in
+    /// `errors` and keep their defaults.
+    pub fn load(root: &Path, file: Option<&Path>) -> Self {
+        let mut config = Config::default();
+        match file {
+            Some(file) => config.load_file(file),
+            None => {
+                let user_file = dirs::config_dir().map(|dir| dir.join("lazytestr").join(FILE_NAME));
+                for path in user_file.into_iter().chain([root.join(FILE_NAME)]) {
+                    if path.is_file() {
+                        config.load_file(&path);
+                    }
+                }
+            }
+        }
+        match Keymap::new(&config.key_bindings) {
+            Ok(keymap) => config.keymap = keymap,
+            Err(err) => config.errors.push(err),
+        }
+        config.apply_env_vars();
+        // Cargo runs in the workspace root, which may be above the project
+        // root lazytestr was started in.
+        if let Some(dir) = &config.target_dir {
+            let root = root.canonicalize().unwrap_or_else(|_| root.to_path_buf());
+            config.target_dir = Some(root.join(dir));
+        }
+        config.watch_paths.retain(|path| {
+            let exists = root.join(path).exists();
+            if !exists {
+                config
+                    .errors
+                    .push(format!("watch path {} does not exist", path.display()));
+            }
+            exists
+        });
+        config
+    }
+
+    fn load_file(&mut self, path: &Path) {
+        let table = match fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|text| text.parse::<Table>().map_err(|err| err.to_string()))
+        {
+            Ok(table) => table,
+            Err(err) => {
+                self.errors
+                    .push(format!("{}: {}", path.display(), err.trim_end()));
+                return;
+            }
+        };
+        self.files.push(path.to_path_buf());
+        for (key, value) in &table {
+            if let Err(err) = self.apply(key, value) {
+                self.errors.push(format!("{}: {}", path.display(), err));
+            }
+        }
+    }
+
+    fn apply(&mut self, key: &str, value: &Value) -> Result<(), String> {
+        match key {
+            "runner" => {
+                self.runner = match string(key, value)?.as_str() {
+                    "auto" => Runner::Auto,
+                    "cargo" => Runner::Cargo,
+                    "nextest" => Runner::Nextest,
+                    other => {
+                        return Err(format!(
+                            "`runner` must be \"auto\", \"cargo\" or \"nextest\", not \"{}\"",
+                            other
+                        ))
+                    }
+                }
+            }
+            "cargo-args" => self.cargo_args = strings(key, value)?,
+            "features" => self.features = strings(key, value)?,
+            "all-features" => self.all_features = boolean(key, value)?,
+            "libtest-json" => self.libtest_json = boolean(key, value)?,
+            "show-warnings" => self.show_warnings = boolean(key, value)?,
+            "target-dir" => self.target_dir = Some(PathBuf::from(string(key, value)?)),
+            "jobs" => self.jobs = count(key, value)?,
+            "stress-runs" => self.stress_runs = count(key, value)?,
+            "editor" => self.editor = Some(string(key, value)?),
+            "env" => {
+                for (name, value) in table(key, value)? {
+                    let value = string(&format!("env.{}", name), value)?;
+                    self.env.retain(|(existing, _)| existing != name);
+                    self.env.push((name.clone(), value));
+                }
+            }
+            "timeouts" => {
+                for (name, value) in table(key, value)? {
+                    let key = format!("timeouts.{}", name);
+                    match name.as_str() {
+                        "test" => self.test_timeout = Some(seconds(&key, value)?),
+                        "run" => self.run_timeout = Some(seconds(&key, value)?),
+                        _ => return Err(format!("unknown setting `{}`", key)),
+                    }
+                }
+            }
+            "watch" => {
+                for (name, value) in table(key, value)? {
+                    let key = format!("watch.{}", name);
+                    match name.as_str() {
+                        "paths" => {
+                            self.watch_paths = strings(&key, value)?
+                                .into_iter()
+                                .map(PathBuf::from)
+                                .collect()
+                        }
+                        "ignore" => {
+                            let patterns = strings(&key, value)?;
+                            let mut builder = GitignoreBuilder::new("");
+                            for pattern in &patterns {
+                                builder.add_line(None, pattern).map_err(|err| {
+                                    format!("`{}` has an invalid pattern: {}", key, err)
+                                })?;
+                            }
+                            self.watch_ignore = patterns;
+                        }
+                        _ => return Err(format!("unknown setting `{}`", key)),
+                    }
+                }
+            }
+            "keys" => {
+                for (action, value) in table(key, value)? {
+                    let key = format!("keys.{}", action);
+                    if !ACTIONS.iter().any(|(name, _, _)| name == action) {
+                        return Err(format!("unknown action `{}`", key));
+                    }
+                    self.key_bindings
+                        .insert(action.clone(), key_char(&key, value)?);
+                }
+            }
+            "theme" => {
+                for (name, value) in table(key, value)? {
+                    let key = format!("theme.{}", name);
+                    let color = color(&key, value)?;
+                    match name.as_str() {
+                        "active-border" => self.theme.active_border = color,
+                        "selection" => self.theme.selection = color,
+                        "passed" => self.theme.passed = color,
+                        "failed" => self.theme.failed = color,
+                        "running" => self.theme.running = color,
+                        _ => return Err(format!("unknown setting `{}`", key)),
+                    }
+                }
+            }
+            _ => return Err(format!("unknown setting `{}`", key)),
+        }
+        Ok(())
+    }
+
+    /// The environment variables lazytestr was configured with before it
+    /// had a config file.
+    fn apply_env_vars(&mut self) {
+        let var = |name: &str| env::var(name).ok().map(|value| (name.to_string(), value));
+        if let Some((_, value)) = var("LAZYTESTR_LIBTEST_JSON") {
+            self.libtest_json = value == "1";
+        }
+        if let Some((name, value)) = var("LAZYTESTR_JOBS") {
+            match value.parse() {
+                Ok(jobs) if jobs > 0 => self.jobs = jobs,
+                _ => self.errors.push(format!(
+                    "ignoring {}={}: expected a positive number",
+                    name, value
+                )),
+            }
+        }
+        if let Some((name, value)) = var("LAZYTESTR_STRESS_RUNS") {
+            match value.parse() {
+                Ok(runs) if runs > 0 => self.stress_runs = runs,
+                _ => self.errors.push(format!(
+                    "ignoring {}={}: expected a positive number",
+                    name, value
+                )),
+            }
+        }
+        for (name, timeout) in [
+            ("LAZYTESTR_TEST_TIMEOUT", &mut self.test_timeout),
+            ("LAZYTESTR_RUN_TIMEOUT", &mut self.run_timeout),
+        ] {
+            let Ok(value) = env::var(name) else {
+                continue;
+            };
+            match value.parse::<f64>() {
+                Ok(secs) if secs > 0.0 => *timeout = Some(Duration::from_secs_f64(secs)),
+                _ => self.errors.push(format!(
+                    "ignoring {}={}: expected a number of seconds",
+                    name, value
+                )),
+            }
+        }
+        if let Some((_, value)) = var("LAZYTESTR_EDITOR") {
+            self.editor = Some(value);
+        }
+    }
+
+    /// Arguments for every cargo build and run: the target directory, the
+    /// features, then the extra arguments.
+    pub fn build_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(dir) = &self.target_dir {
+            args.push("--target-dir".to_string());
+            args.push(dir.display().to_string());
+        }
+        if self.all_features {
+            args.push("--all-features".to_string());
+        } else if !self.features.is_empty() {
+            args.push("--features".to_string());
+            args.push(self.features.join(","));
+        }
+        args.extend(self.cargo_args.iter().cloned());
+        args
+    }
+}
+
+fn string(key: &str, value: &Value) -> Result<String, String> {
+    value
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| format!("`{}` must be a string, not {}", key, value.type_str()))
+}
+
+fn strings(key: &str, value: &Value) -> Result<Vec<String>, String> {
+    value
+        .as_array()
+        .and_then(|items| {
+            items
+                .iter()
+                .map(|item| item.as_str().map(str::to_string))
+                .collect()
+        })
+        .ok_or_else(|| format!("`{}` must be a list of strings", key))
+}
+
+fn boolean(key: &str, value: &Value) -> Result<bool, String> {
+    value
+        .as_bool()
+        .ok_or_else(|| format!("`{}` must be true or false, not {}", key, value.type_str()))
+}
+
+fn count(key: &str, value: &Value) -> Result<usize, String> {
+    value
+        .as_integer()
+        .filter(|count| *count > 0)
+        .map(|count| count as usize)
+        .ok_or_else(|| format!("`{}` must be a positive integer", key))
+}
+
+fn seconds(key: &str, value: &Value) -> Result<Duration, String> {
+    value
+        .as_float()
+        .or_else(|| value.as_integer().map(|secs| secs as f64))
+        .filter(|secs| *secs > 0.0)
+        .map(Duration::from_secs_f64)
+        .ok_or_else(|| format!("`{}` must be a positive number of seconds", key))
+}
+
+fn table<'a>(key: &str, value: &'a Value) -> Result<&'a Table, String> {
+    value
+        .as_table()
+        .ok_or_else(|| format!("`{}` must be a table, not {}", key, value.type_str()))
+}
+
+/// A key given as a single character, or `space`.
+fn key_char(key: &str, value: &Value) -> Result<char, String> {
+    let text = string(key, value)?;
+    if text == "space" {
+        return Ok(' ');
+    }
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(c),
+        _ => Err(format!(
+            "`{}` must be a single character or \"space\", not \"{}\"",
+            key, text
+        )),
+    }
+}
+
+/// A color name such as `yellow` or `light-blue`, or `#rrggbb`.
+fn color(key: &str, value: &Value) -> Result<Color, String> {
+    let text = string(key, value)?;
+    if let Some(hex) = text.strip_prefix('#') {
+        let channel = |range| u8::from_str_radix(hex.get(range)?, 16).ok();
+        if let (6, Some(r), Some(g), Some(b)) =
+            (hex.len(), channel(0..2), channel(2..4), channel(4..6))
+        {
+            return Ok(Color::Rgb(r, g, b));
+        }
+    }
+    let color = match text.to_lowercase().replace(['-', '_'], "").as_str() {
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "gray" | "grey" => Color::Gray,
+        "darkgray" | "darkgrey" => Color::DarkGray,
+        "lightred" => Color::LightRed,
+        "lightgreen" => Color::LightGreen,
+        "lightyellow" => Color::LightYellow,
+        "lightblue" => Color::LightBlue,
+        "lightmagenta" => Color::LightMagenta,
+        "lightcyan" => Color::LightCyan,
+        "white" => Color::White,
+        _ => {
+            return Err(format!(
+                "`{}` must be a color name or \"#rrggbb\", not \"{}\"",
+                key, text
+            ))
+        }
+    };
+    Ok(color)
+}
+
+/// Keys moved to other characters by the `[keys]` table.
+#[derive(Clone, Default)]
+pub struct Keymap {
+    /// Rebound keys, with the built-in key of the action they trigger and
+    /// where.
+    bound: Vec<(char, char, Panes)>,
+    /// The built-in keys of rebound actions, which no longer trigger them.
+    moved: Vec<(char, Panes)>,
+}
+
+impl Keymap {
+    fn new(bindings: &HashMap<String, char>) -> Result<Self, String> {
+        let mut keymap = Keymap::default();
+        let mut owners: Vec<(char, &str, Panes)> = ACTIONS
+            .iter()
+            .filter(|(name, _, _)| !bindings.contains_key(*name))
+            .map(|(name, key, panes)| (*key, *name, *panes))
+            .collect();
+        for (name, default, panes) in ACTIONS {
+            let Some(key) = bindings.get(*name) else {
+                continue;
+            };
+            if let Some((_, owner, _)) = owners
+                .iter()
+                .find(|(owned, _, owner_panes)| owned == key && owner_panes.overlaps(*panes))
+            {
+                return Err(format!(
+                    "`keys.{}` uses '{}', which is already bound to {}",
+                    name, key, owner
+                ));
+            }
+            owners.push((*key, name, *panes));
+            keymap.moved.push((*default, *panes));
+            keymap.bound.push((*key, *default, *panes));
+        }
+        Ok(keymap)
+    }
+
+    /// The built-in key for a key pressed in the output pane or elsewhere,
+    /// or `None` when the action it used to trigger there has been moved to
+    /// another key.
+    pub fn translate(&self, key: KeyCode, output: bool) -> Option<KeyCode> {
+        let KeyCode::Char(c) = key else {
+            return Some(key);
+        };
+        let bound = self
+            .bound
+            .iter()
+            .find(|(bound, _, panes)| *bound == c && panes.includes(output));
+        match bound {
+            Some((_, default, _)) => Some(KeyCode::Char(*default)),
+            None if self
+                .moved
+                .iter()
+                .any(|(moved, panes)| *moved == c && panes.includes(output)) =>
+            {
+                None
+            }
+            None => Some(key),
+        }
+    }
+}
+
+/// Colors of the UI that the `[theme]` table can change.
+#[derive(Clone, Copy)]
+pub struct Theme {
+    pub active_border: Color,
+    /// Background of the selected row.
+    pub selection: Color,
+    pub passed: Color,
+    pub failed: Color,
+    pub running: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            active_border: Color::Rgb(255, 165, 0),
+            selection: Color::LightBlue,
+            passed: Color::Green,
+            failed: Color::Red,
+            running: Color::Yellow,
+        }
+    }
+}
+
+/// Adds the configured variables to the inherited environment, which is
+/// otherwise passed on to cargo as is: flags such as `RUSTFLAGS` are part of
+/// cargo's build fingerprint, so changing them would rebuild everything the
+/// user's own `cargo test` built.
+pub fn setup_environment(config: &Config, log_file: &Path) -> Result<(), Box<dyn Error>> {
+    for (name, value) in &config.env {
+        env::set_var(name, value);
+    }
 
     WriteLogger::init(
         LevelFilter::Debug,
-        Config::default(),
-        File::create("debug.log")?,
+        LogConfig::default(),
+        File::create(log_file)?,
     )?;
+    for file in &config.files {
+        info!("Loaded settings from {}", file.display());
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Applies the settings of a config file, returning the errors.
+    fn parse(text: &str) -> (Config, Vec<String>) {
+        let mut config = Config::default();
+        let mut errors = Vec::new();
+        for (key, value) in &text.parse::<Table>().unwrap() {
+            if let Err(err) = config.apply(key, value) {
+                errors.push(err);
+            }
+        }
+        (config, errors)
+    }
+
+    fn keymap(bindings: &[(&str, char)]) -> Result<Keymap, String> {
+        let bindings = bindings
+            .iter()
+            .map(|(action, key)| (action.to_string(), *key))
+            .collect();
+        Keymap::new(&bindings)
+    }
+
+    #[test]
+    fn settings() {
+        let (config, errors) = parse(
+            r##"
+            runner = "cargo"
+            cargo-args = ["--release"]
+            features = ["serde", "tokio"]
+            libtest-json = true
+            target-dir = "target/lazytestr"
+            jobs = 4
+            editor = "code --goto {file}:{line}"
+
+            [env]
+            RUST_LOG = "debug"
+
+            [timeouts]
+            test = 2.5
+            run = 60
+
+            [watch]
+            paths = ["src", "tests"]
+            ignore = ["*.snap"]
+
+            [theme]
+            passed = "light-green"
+            failed = "#ff8000"
+            "##,
+        );
+        assert_eq!(errors, Vec::<String>::new());
+        assert!(config.runner == Runner::Cargo);
+        assert!(config.libtest_json);
+        assert_eq!(config.jobs, 4);
+        assert_eq!(config.stress_runs, 20);
+        assert_eq!(config.env, [("RUST_LOG".to_string(), "debug".to_string())]);
+        assert_eq!(config.test_timeout, Some(Duration::from_millis(2500)));
+        assert_eq!(config.run_timeout, Some(Duration::from_secs(60)));
+        assert_eq!(
+            config.watch_paths,
+            [PathBuf::from("src"), PathBuf::from("tests")]
+        );
+        assert_eq!(config.watch_ignore, ["*.snap"]);
+        assert_eq!(config.theme.passed, Color::LightGreen);
+        assert_eq!(config.theme.failed, Color::Rgb(255, 128, 0));
+        assert_eq!(config.theme.running, Color::Yellow);
+        assert_eq!(
+            config.build_args(),
+            [
+                "--target-dir",
+                "target/lazytestr",
+                "--features",
+                "serde,tokio",
+                "--release"
+            ]
+        );
+    }
+
+    #[test]
+    fn all_features_wins_over_features() {
+        let (config, _) = parse("features = [\"serde\"]\nall-features = true");
+        assert_eq!(config.build_args(), ["--all-features"]);
+        assert!(Config::default().build_args().is_empty());
+    }
+
+    #[test]
+    fn invalid_settings_keep_their_defaults() {
+        let (config, errors) = parse(
+            r##"
+            runner = "make"
+            jobs = 0
+            show-warnings = "yes"
+            colour = "red"
+            timeouts = { test = -1 }
+            theme = { passed = "#12345" }
+            "##,
+        );
+        assert_eq!(
+            errors,
+            [
+                "unknown setting `colour`",
+                "`jobs` must be a positive integer",
+                "`runner` must be \"auto\", \"cargo\" or \"nextest\", not \"make\"",
+                "`show-warnings` must be true or false, not string",
+                "`theme.passed` must be a color name or \"#rrggbb\", not \"#12345\"",
+                "`timeouts.test` must be a positive number of seconds",
+            ]
+        );
+        assert!(config.runner == Runner::Auto);
+        assert_eq!(config.jobs, 1);
+        assert!(!config.show_warnings);
+        assert_eq!(config.test_timeout, None);
+        assert_eq!(config.theme.passed, Color::Green);
+    }
+
+    #[test]
+    fn invalid_table_entries() {
+        let error = |text: &str| parse(text).1.join("\n");
+        assert_eq!(
+            error("timeouts = { suite = 5 }"),
+            "unknown setting `timeouts.suite`"
+        );
+        assert_eq!(error("keys = { fly = \"f\" }"), "unknown action `keys.fly`");
+        assert_eq!(
+            error("keys = { quit = \"Q!\" }"),
+            "`keys.quit` must be a single character or \"space\", not \"Q!\""
+        );
+        assert_eq!(
+            error("env = { A = 1 }"),
+            "`env.A` must be a string, not integer"
+        );
+        assert_eq!(
+            error("watch = [\"src\"]"),
+            "`watch` must be a table, not array"
+        );
+        assert_eq!(
+            error("watch = { paths = \"src\" }"),
+            "`watch.paths` must be a list of strings"
+        );
+
+        let (config, errors) = parse("watch = { ignore = [\"*.snap\", \"src/[z-a]\"] }");
+        assert!(errors[0].starts_with("`watch.ignore` has an invalid pattern: "));
+        assert_eq!(config.watch_ignore, ["target/", ".git/"]);
+    }
+
+    #[test]
+    fn later_env_tables_override() {
+        let mut config = Config::default();
+        for text in ["[env]\nA = \"1\"\nB = \"2\"", "[env]\nA = \"3\""] {
+            for (key, value) in &text.parse::<Table>().unwrap() {
+                config.apply(key, value).unwrap();
+            }
+        }
+        assert_eq!(
+            config.env,
+            [
+                ("B".to_string(), "2".to_string()),
+                ("A".to_string(), "3".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn rebound_keys() {
+        let keymap = keymap(&[("quit", 'Q'), ("toggle-node", ' '), ("filter", 'z')]).unwrap();
+        assert_eq!(
+            keymap.translate(KeyCode::Char('Q'), false),
+            Some(KeyCode::Char('q'))
+        );
+        assert_eq!(keymap.translate(KeyCode::Char('q'), false), None);
+        assert_eq!(
+            keymap.translate(KeyCode::Char('z'), false),
+            Some(KeyCode::Char('/'))
+        );
+        assert_eq!(keymap.translate(KeyCode::Char('/'), false), None);
+        // Searching the output keeps its key.
+        assert_eq!(
+            keymap.translate(KeyCode::Char('/'), true),
+            Some(KeyCode::Char('/'))
+        );
+        assert_eq!(
+            keymap.translate(KeyCode::Char(' '), false),
+            Some(KeyCode::Char(' '))
+        );
+        assert_eq!(
+            keymap.translate(KeyCode::Char('j'), false),
+            Some(KeyCode::Char('j'))
+        );
+        assert_eq!(
+            keymap.translate(KeyCode::Enter, false),
+            Some(KeyCode::Enter)
+        );
+    }
+
+    #[test]
+    fn pane_keys() {
+        let keymap = keymap(&[("search", '?'), ("filter", 'n')]).unwrap();
+        assert_eq!(
+            keymap.translate(KeyCode::Char('?'), true),
+            Some(KeyCode::Char('/'))
+        );
+        assert_eq!(keymap.translate(KeyCode::Char('/'), true), None);
+        assert_eq!(
+            keymap.translate(KeyCode::Char('n'), false),
+            Some(KeyCode::Char('/'))
+        );
+        assert_eq!(
+            keymap.translate(KeyCode::Char('n'), true),
+            Some(KeyCode::Char('n'))
+        );
+        assert_eq!(
+            keymap.translate(KeyCode::Char('?'), false),
+            Some(KeyCode::Char('?'))
+        );
+    }
+
+    #[test]
+    fn swapped_keys() {
+        let keymap = keymap(&[("quit", 'a'), ("run-all", 'q')]).unwrap();
+        assert_eq!(
+            keymap.translate(KeyCode::Char('a'), false),
+            Some(KeyCode::Char('q'))
+        );
+        assert_eq!(
+            keymap.translate(KeyCode::Char('q'), false),
+            Some(KeyCode::Char('a'))
+        );
+    }
+
+    #[test]
+    fn conflicting_keys() {
+        assert_eq!(
+            keymap(&[("run-all", 'q')]).err().as_deref(),
+            Some("`keys.run-all` uses 'q', which is already bound to quit")
+        );
+        assert_eq!(
+            keymap(&[("quit", 'Q'), ("rescan", 'Q')]).err().as_deref(),
+            Some("`keys.rescan` uses 'Q', which is already bound to quit")
+        );
+        assert_eq!(
+            keymap(&[("page-down", 'c')]).err().as_deref(),
+            Some("`keys.page-down` uses 'c', which is already bound to compare")
+        );
+    }
+
+    #[test]
+    fn key_names() {
+        let (config, errors) = parse("[keys]\ntoggle-node = \"space\"\nexpand-all = \"=\"");
+        assert_eq!(errors, Vec::<String>::new());
+        assert_eq!(config.key_bindings["toggle-node"], ' ');
+        assert_eq!(config.key_bindings["expand-all"], '=');
+    }
+}
@@ -0,0 +1,88 @@
+use crate::ui::{restore_terminal, resume_terminal};
+use std::env;
+use std::error::Error;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use tui::{backend::CrosstermBackend, Terminal};
+
+/// A place in a source file, with one-based line and column.
+#[derive(Clone)]
+pub struct Location {
+    pub path: PathBuf,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Opens the location in the editor and waits for it to exit, with the TUI
+/// suspended in the meantime.
+pub fn open_in_editor(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    location: &Location,
+    template: Option<&str>,
+) -> Result<(), Box<dyn Error>> {
+    let mut cmd = editor_command(location, template);
+    restore_terminal(terminal)?;
+    let status = cmd.status();
+    resume_terminal(terminal)?;
+    status?;
+    Ok(())
+}
+
+/// Builds the editor invocation. The configured `editor` is a command
+/// template with `{file}`, `{line}` and `{column}` placeholders; otherwise
+/// `$VISUAL` or `$EDITOR` (`vi` when neither is set) is called the way that
+/// editor expects a position to be passed.
+fn editor_command(location: &Location, template: Option<&str>) -> Command {
+    if let Some(template) = template {
+        let args: Vec<String> = template
+            .split_whitespace()
+            .map(|arg| {
+                arg.replace("{file}", &location.path.to_string_lossy())
+                    .replace("{line}", &location.line.to_string())
+                    .replace("{column}", &location.column.to_string())
+            })
+            .collect();
+        if let Some((program, args)) = args.split_first() {
+            let mut cmd = Command::new(program);
+            cmd.args(args);
+            return cmd;
+        }
+    }
+
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    // Allow editors configured with arguments, such as `code --wait`.
+    let mut parts = editor.split_whitespace();
+    let program = parts.next().unwrap_or("vi");
+    let mut cmd = Command::new(program);
+    cmd.args(parts);
+
+    let path = location.path.display();
+    let (line, column) = (location.line, location.column);
+    let name = Path::new(program)
+        .file_stem()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    match name.as_str() {
+        "code" | "code-insiders" | "codium" | "cursor" => {
+            cmd.arg("--goto")
+                .arg(format!("{}:{}:{}", path, line, column));
+        }
+        "hx" | "helix" | "zed" | "subl" => {
+            cmd.arg(format!("{}:{}:{}", path, line, column));
+        }
+        "vim" | "nvim" | "gvim" => {
+            cmd.arg(format!("+call cursor({}, {})", line, column))
+                .arg(&location.path);
+        }
+        "emacs" | "emacsclient" => {
+            cmd.arg(format!("+{}:{}", line, column)).arg(&location.path);
+        }
+        _ => {
+            cmd.arg(format!("+{}", line)).arg(&location.path);
+        }
+    }
+    cmd
+}
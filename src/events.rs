@@ -0,0 +1,619 @@
+use crate::app::TestStatus;
+use crate::editor::Location;
+use crate::history::Revision;
+use crate::test_runner::RunSpec;
+use regex::Regex;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// What the runner reports to the app while tests build and run.
+pub enum RunnerEvent {
+    /// A line of output that is not a structured event.
+    Output(String),
+    /// An event of a job from the run queue.
+    Job {
+        job: usize,
+        event: Box<RunnerEvent>,
+    },
+    /// The job has started, with the commands it runs and the sources it
+    /// tests for the history.
+    RunStarted {
+        specs: Vec<RunSpec>,
+        /// How many times the specs run; more than once for stress runs.
+        repeat: usize,
+        commands: Vec<String>,
+        revision: Option<Revision>,
+    },
+    SuiteStarted {
+        test_count: usize,
+    },
+    TestStarted {
+        name: String,
+    },
+    TestFinished(TestResult),
+    /// A line of output captured while the named test ran.
+    TestOutput {
+        /// The target of the test binary, when the output names it.
+        binary: Option<String>,
+        name: String,
+        line: String,
+    },
+    /// A compiler error or warning from building the tests.
+    Diagnostic(Diagnostic),
+    /// The tests still running in a suite were killed for taking too long.
+    /// Only sent when the harness does not say which tests are running.
+    SuiteTimedOut {
+        binary: Option<String>,
+        ran_for: Duration,
+    },
+    /// The last test process of the run has exited or was killed.
+    RunFinished {
+        success: bool,
+        description: String,
+    },
+}
+
+impl RunnerEvent {
+    pub fn for_job(self, job: usize) -> RunnerEvent {
+        RunnerEvent::Job {
+            job,
+            event: Box::new(self),
+        }
+    }
+}
+
+pub struct TestResult {
+    /// The target of the test binary, when the output names it. Otherwise
+    /// it is the one of cargo's last `Running` line.
+    pub binary: Option<String>,
+    pub name: String,
+    pub status: TestStatus,
+    /// Captured output, when the harness reports it with the result.
+    pub stdout: Option<String>,
+    pub duration: Option<Duration>,
+    /// The line the result was parsed from, for human-readable formats.
+    pub line: Option<String>,
+}
+
+#[derive(Clone)]
+pub struct Diagnostic {
+    /// `error` or `warning`.
+    pub level: String,
+    pub message: String,
+    /// Relative to the workspace root, for diagnostics that point at code.
+    pub path: Option<PathBuf>,
+    pub line: usize,
+    pub column: usize,
+    /// The diagnostic as rustc prints it, with ANSI colors.
+    pub rendered: String,
+}
+
+impl Diagnostic {
+    pub fn is_error(&self) -> bool {
+        self.level == "error"
+    }
+}
+
+/// Turns lines of test output into events. Understands cargo's JSON build
+/// messages, libtest's JSON format (including nextest's `libtest-json`) as
+/// well as the human-readable output of libtest and nextest. Build messages
+/// other than diagnostics are dropped.
+pub fn parse_line(line: &str) -> Option<RunnerEvent> {
+    if line.starts_with('{') {
+        if let Ok(event) = serde_json::from_str::<Value>(line) {
+            if let Some(reason) = event["reason"].as_str() {
+                return match reason {
+                    "compiler-message" => parse_diagnostic(&event["message"]),
+                    _ => None,
+                };
+            }
+            if let Some(event) = parse_json_event(&event) {
+                return Some(event);
+            }
+        }
+    }
+    if let Some(test_count) = parse_suite_start(line) {
+        return Some(RunnerEvent::SuiteStarted { test_count });
+    }
+    Some(match parse_result_line(line) {
+        Some(result) => RunnerEvent::TestFinished(result),
+        None => RunnerEvent::Output(line.to_string()),
+    })
+}
+
+fn parse_diagnostic(message: &Value) -> Option<RunnerEvent> {
+    let span = message["spans"]
+        .as_array()
+        .and_then(|spans| spans.iter().find(|span| span["is_primary"] == true));
+    Some(RunnerEvent::Diagnostic(Diagnostic {
+        level: message["level"].as_str()?.to_string(),
+        message: message["message"].as_str()?.to_string(),
+        path: span.and_then(|span| span["file_name"].as_str().map(PathBuf::from)),
+        line: span
+            .and_then(|span| span["line_start"].as_u64())
+            .unwrap_or(1) as usize,
+        column: span
+            .and_then(|span| span["column_start"].as_u64())
+            .unwrap_or(1) as usize,
+        rendered: message["rendered"].as_str().unwrap_or_default().to_string(),
+    }))
+}
+
+/// Parses the lines of one output stream, following the sections in which
+/// libtest (`---- name stdout ----`) and nextest (`STDOUT: binary name`)
+/// print the captured output of a test.
+#[derive(Default)]
+pub struct LineParser {
+    /// The binary target, if named, and the test of the current section.
+    section: Option<(Option<String>, String)>,
+}
+
+impl LineParser {
+    pub fn parse(&mut self, line: &str) -> Option<RunnerEvent> {
+        static HEADER: OnceLock<Regex> = OnceLock::new();
+        let header = HEADER.get_or_init(|| {
+            Regex::new(
+                r"^(?:---- (\S+) stdout ----|\s*(?:---|─+) (?:STDOUT|STDERR):\s+(\S+)\s+(\S+?)(?: ---)?)\s*$",
+            )
+            .unwrap()
+        });
+
+        let plain = strip_ansi(line);
+        if let Some(caps) = header.captures(&plain) {
+            self.section = match (caps.get(1), caps.get(2), caps.get(3)) {
+                (Some(name), _, _) => Some((None, name.as_str().to_string())),
+                (_, Some(binary), Some(name)) => Some((
+                    Some(binary_target(binary.as_str())),
+                    name.as_str().to_string(),
+                )),
+                _ => None,
+            };
+            return Some(RunnerEvent::Output(line.to_string()));
+        }
+        let event = parse_line(line)?;
+        let ends_section = match &event {
+            RunnerEvent::Output(_) => {
+                let plain = plain.trim();
+                plain == "successes:" || plain == "failures:" || plain.starts_with("test result:")
+            }
+            _ => true,
+        };
+        if ends_section {
+            self.section = None;
+        }
+        match &self.section {
+            Some((binary, name)) => Some(RunnerEvent::TestOutput {
+                binary: binary.clone(),
+                name: name.clone(),
+                line: line.to_string(),
+            }),
+            None => Some(event),
+        }
+    }
+}
+
+/// The test and location of a panic message such as
+/// `thread 'foo::bar' (1234) panicked at src/foo.rs:42:5:`.
+pub fn parse_panic(line: &str) -> Option<(String, Location)> {
+    static PANIC: OnceLock<Regex> = OnceLock::new();
+    let panic = PANIC.get_or_init(|| {
+        Regex::new(r"thread '([^']+)'(?: \(\d+\))? panicked at (?:'.*', )?(.+?):(\d+):(\d+)")
+            .unwrap()
+    });
+    let line = strip_ansi(line);
+    let caps = panic.captures(&line)?;
+    let location = Location {
+        path: PathBuf::from(&caps[2]),
+        line: caps[3].parse().ok()?,
+        column: caps[4].parse().ok()?,
+    };
+    Some((caps[1].to_string(), location))
+}
+
+/// The first `path/to/file.rs:line:column` reference in a line of output.
+pub fn find_location(line: &str) -> Option<Location> {
+    static LOCATION: OnceLock<Regex> = OnceLock::new();
+    let location = LOCATION.get_or_init(|| Regex::new(r"([\w./\\-]+\.rs):(\d+):(\d+)").unwrap());
+    let line = strip_ansi(line);
+    let caps = location.captures(&line)?;
+    Some(Location {
+        path: PathBuf::from(&caps[1]),
+        line: caps[2].parse().ok()?,
+        column: caps[3].parse().ok()?,
+    })
+}
+
+pub fn strip_ansi(line: &str) -> std::borrow::Cow<'_, str> {
+    static ANSI: OnceLock<Regex> = OnceLock::new();
+    let ansi = ANSI.get_or_init(|| Regex::new(r"\x1b\[[0-9;]*m").unwrap());
+    ansi.replace_all(line, "")
+}
+
+/// libtest's `running 3 tests` line.
+fn parse_suite_start(line: &str) -> Option<usize> {
+    let count = line.strip_prefix("running ")?;
+    let count = count
+        .strip_suffix(" tests")
+        .or_else(|| count.strip_suffix(" test"))?;
+    count.parse().ok()
+}
+
+fn parse_json_event(event: &Value) -> Option<RunnerEvent> {
+    let kind = event["event"].as_str()?;
+    match event["type"].as_str()? {
+        "suite" if kind == "started" => Some(RunnerEvent::SuiteStarted {
+            test_count: event["test_count"].as_u64().unwrap_or(0) as usize,
+        }),
+        // Render the suite summary like libtest's human-readable output.
+        "suite" => Some(RunnerEvent::Output(format!(
+            "test result: {}. {} passed; {} failed; {} ignored",
+            kind,
+            event["passed"].as_u64().unwrap_or(0),
+            event["failed"].as_u64().unwrap_or(0),
+            event["ignored"].as_u64().unwrap_or(0),
+        ))),
+        "test" => {
+            let (binary, name) = test_name(event["name"].as_str()?);
+            let status = match kind {
+                "started" => return Some(RunnerEvent::TestStarted { name }),
+                "ok" => TestStatus::Passed,
+                "failed" => TestStatus::Failed,
+                "ignored" => TestStatus::Ignored,
+                "timeout" => TestStatus::TimedOut,
+                _ => return None,
+            };
+            Some(RunnerEvent::TestFinished(TestResult {
+                binary,
+                name,
+                status,
+                stdout: event["stdout"].as_str().map(str::to_string),
+                duration: event["exec_time"].as_f64().map(Duration::from_secs_f64),
+                line: None,
+            }))
+        }
+        _ => None,
+    }
+}
+
+/// nextest prefixes test names with the binary id, as in `my-crate$tests::foo`.
+/// Returns the binary's target, if any, and the test name.
+fn test_name(name: &str) -> (Option<String>, String) {
+    match name.split_once('$') {
+        Some((binary, name)) => (Some(binary_target(binary)), name.to_string()),
+        None => (None, name.to_string()),
+    }
+}
+
+/// The target of a nextest binary id: `my-crate` for the library of
+/// `my-crate`, `it` for `my-crate::it` and `cli` for `my-crate::bin/cli`.
+fn binary_target(binary_id: &str) -> String {
+    let target = binary_id
+        .split_once("::")
+        .map_or(binary_id, |(_, target)| target);
+    target.rsplit('/').next().unwrap_or(target).to_string()
+}
+
+/// The target of the test binary in cargo's
+/// `Running unittests src/lib.rs (target/debug/deps/my_crate-1a2b3c)` line.
+pub fn running_binary(line: &str) -> Option<String> {
+    static RUNNING: OnceLock<Regex> = OnceLock::new();
+    let running = RUNNING.get_or_init(|| Regex::new(r"^\s*Running .*\((.+)\)$").unwrap());
+    let line = strip_ansi(line);
+    let caps = running.captures(&line)?;
+    let stem = Path::new(&caps[1])
+        .file_stem()?
+        .to_string_lossy()
+        .to_string();
+    Some(match stem.rsplit_once('-') {
+        Some((name, _hash)) => name.to_string(),
+        None => stem,
+    })
+}
+
+/// Whether two target names are the same, as binaries name them with
+/// underscores and packages may use dashes.
+pub fn same_target(a: &str, b: &str) -> bool {
+    a.replace('-', "_") == b.replace('-', "_")
+}
+
+/// Extracts a test outcome from a line of human-readable libtest or nextest
+/// output, such as `test foo::bar ... ok` or `PASS [   0.004s] pkg foo::bar`.
+fn parse_result_line(raw: &str) -> Option<TestResult> {
+    static LIBTEST: OnceLock<Regex> = OnceLock::new();
+    static NEXTEST: OnceLock<Regex> = OnceLock::new();
+
+    let line = strip_ansi(raw);
+
+    let libtest = LIBTEST.get_or_init(|| {
+        Regex::new(
+            r"^test (\S+)(?: - should panic)? \.\.\. (ok|FAILED|ignored)(?:.*? <([\d.]+)s>)?",
+        )
+        .unwrap()
+    });
+    if let Some(caps) = libtest.captures(&line) {
+        let status = match &caps[2] {
+            "ok" => TestStatus::Passed,
+            "FAILED" => TestStatus::Failed,
+            _ => TestStatus::Ignored,
+        };
+        return Some(TestResult {
+            binary: None,
+            name: caps[1].to_string(),
+            status,
+            stdout: None,
+            // Only printed with `-Z unstable-options --report-time`.
+            duration: caps
+                .get(3)
+                .and_then(|time| time.as_str().parse().ok())
+                .map(Duration::from_secs_f64),
+            line: Some(raw.to_string()),
+        });
+    }
+
+    let nextest = NEXTEST.get_or_init(|| {
+        Regex::new(
+            r"^\s*(PASS|FAIL|TIMEOUT|SKIP|SIGSEGV|SIGABRT|SIGBUS|SIGILL|ABORT|LEAK-FAIL)\s+\[\s*([\d.]+)s\]\s+(?:\(\s*\d+/\d+\)\s+)?(\S+)\s+(\S+)\s*$",
+        )
+        .unwrap()
+    });
+    let caps = nextest.captures(&line)?;
+    let status = match &caps[1] {
+        "PASS" => TestStatus::Passed,
+        "TIMEOUT" => TestStatus::TimedOut,
+        "SKIP" => TestStatus::Ignored,
+        _ => TestStatus::Failed,
+    };
+    Some(TestResult {
+        binary: Some(binary_target(&caps[3])),
+        name: caps[4].to_string(),
+        status,
+        stdout: None,
+        duration: caps[2].parse().ok().map(Duration::from_secs_f64),
+        line: Some(raw.to_string()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn finished(event: Option<RunnerEvent>) -> TestResult {
+        match event {
+            Some(RunnerEvent::TestFinished(result)) => result,
+            _ => panic!("not a test result"),
+        }
+    }
+
+    /// The binary, name and line of the `TestOutput` events of some output.
+    fn captured(lines: &[&str]) -> Vec<(Option<String>, String, String)> {
+        let mut parser = LineParser::default();
+        lines
+            .iter()
+            .filter_map(|line| match parser.parse(line) {
+                Some(RunnerEvent::TestOutput { binary, name, line }) => Some((binary, name, line)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn libtest_results() {
+        let result = finished(parse_line("test tests::adds ... ok"));
+        assert_eq!(result.name, "tests::adds");
+        assert!(result.status == TestStatus::Passed);
+        assert_eq!(result.binary, None);
+        assert_eq!(result.line.as_deref(), Some("test tests::adds ... ok"));
+
+        let result = finished(parse_line(
+            "test tests::overflows - should panic ... FAILED",
+        ));
+        assert_eq!(result.name, "tests::overflows");
+        assert!(result.status == TestStatus::Failed);
+
+        let result = finished(parse_line("test tests::slow ... ignored, needs a network"));
+        assert!(result.status == TestStatus::Ignored);
+
+        let result = finished(parse_line("test tests::timed ... ok <0.250s>"));
+        assert_eq!(result.duration, Some(Duration::from_millis(250)));
+    }
+
+    #[test]
+    fn libtest_suite_lines() {
+        assert!(matches!(
+            parse_line("running 3 tests"),
+            Some(RunnerEvent::SuiteStarted { test_count: 3 })
+        ));
+        assert!(matches!(
+            parse_line("running 1 test"),
+            Some(RunnerEvent::SuiteStarted { test_count: 1 })
+        ));
+        assert!(matches!(
+            parse_line("test result: ok. 3 passed; 0 failed; 0 ignored"),
+            Some(RunnerEvent::Output(_))
+        ));
+    }
+
+    #[test]
+    fn libtest_json() {
+        assert!(matches!(
+            parse_line(r#"{ "type": "suite", "event": "started", "test_count": 2 }"#),
+            Some(RunnerEvent::SuiteStarted { test_count: 2 })
+        ));
+        assert!(matches!(
+            parse_line(r#"{ "type": "test", "event": "started", "name": "tests::adds" }"#),
+            Some(RunnerEvent::TestStarted { name }) if name == "tests::adds"
+        ));
+
+        let result = finished(parse_line(
+            r#"{ "type": "test", "name": "tests::adds", "event": "ok", "exec_time": 0.5 }"#,
+        ));
+        assert_eq!(result.name, "tests::adds");
+        assert!(result.status == TestStatus::Passed);
+        assert_eq!(result.duration, Some(Duration::from_millis(500)));
+        assert_eq!(result.line, None);
+
+        let result = finished(parse_line(
+            r#"{ "type": "test", "name": "tests::fails", "event": "failed", "stdout": "thread 'tests::fails' panicked at src/lib.rs:9:9:\nboom\n" }"#,
+        ));
+        assert!(result.status == TestStatus::Failed);
+        assert!(result.stdout.unwrap().contains("boom"));
+
+        match parse_line(
+            r#"{ "type": "suite", "event": "failed", "passed": 1, "failed": 1, "ignored": 0 }"#,
+        ) {
+            Some(RunnerEvent::Output(line)) => {
+                assert_eq!(line, "test result: failed. 1 passed; 1 failed; 0 ignored")
+            }
+            _ => panic!("not a summary"),
+        }
+    }
+
+    #[test]
+    fn nextest_results() {
+        let result = finished(parse_line("        PASS [   0.004s] my-crate tests::adds"));
+        assert_eq!(result.name, "tests::adds");
+        assert_eq!(result.binary.as_deref(), Some("my-crate"));
+        assert!(result.status == TestStatus::Passed);
+        assert_eq!(result.duration, Some(Duration::from_millis(4)));
+
+        let result = finished(parse_line(
+            "        FAIL [   1.250s] (2/5) my-crate::it m::n",
+        ));
+        assert_eq!(result.name, "m::n");
+        assert_eq!(result.binary.as_deref(), Some("it"));
+        assert!(result.status == TestStatus::Failed);
+
+        let result = finished(parse_line(
+            "     TIMEOUT [  60.001s] my-crate::bin/cli tests::hangs",
+        ));
+        assert_eq!(result.binary.as_deref(), Some("cli"));
+        assert!(result.status == TestStatus::TimedOut);
+
+        let colored = "        \x1b[32;1mPASS\x1b[0m [   0.004s] \x1b[35;1mmy-crate\x1b[0m \x1b[36mtests::\x1b[0m\x1b[34;1madds\x1b[0m";
+        assert_eq!(finished(parse_line(colored)).name, "tests::adds");
+    }
+
+    #[test]
+    fn nextest_libtest_json() {
+        let result = finished(parse_line(
+            r#"{"type":"test","event":"ok","name":"my-crate::it$m::n","exec_time":0.01}"#,
+        ));
+        assert_eq!(result.name, "m::n");
+        assert_eq!(result.binary.as_deref(), Some("it"));
+
+        let result = finished(parse_line(
+            r#"{"type":"test","event":"failed","name":"my-crate$tests::fails"}"#,
+        ));
+        assert_eq!(result.name, "tests::fails");
+        assert_eq!(result.binary.as_deref(), Some("my-crate"));
+    }
+
+    #[test]
+    fn compiler_messages() {
+        let line = r#"{"reason":"compiler-message","message":{"level":"error","message":"mismatched types","rendered":"error[E0308]: mismatched types\n","spans":[{"file_name":"src/lib.rs","line_start":4,"column_start":9,"is_primary":true}]}}"#;
+        match parse_line(line) {
+            Some(RunnerEvent::Diagnostic(diagnostic)) => {
+                assert!(diagnostic.is_error());
+                assert_eq!(diagnostic.path, Some(PathBuf::from("src/lib.rs")));
+                assert_eq!((diagnostic.line, diagnostic.column), (4, 9));
+            }
+            _ => panic!("not a diagnostic"),
+        }
+        assert!(parse_line(r#"{"reason":"compiler-artifact","target":{}}"#).is_none());
+    }
+
+    #[test]
+    fn libtest_output_sections() {
+        let output = captured(&[
+            "failures:",
+            "",
+            "---- tests::fails stdout ----",
+            "thread 'tests::fails' panicked at src/lib.rs:9:9:",
+            "boom",
+            "",
+            "---- tests::other stdout ----",
+            "printed",
+            "",
+            "failures:",
+            "    tests::fails",
+        ]);
+        let names: Vec<&str> = output.iter().map(|(_, name, _)| name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "tests::fails",
+                "tests::fails",
+                "tests::fails",
+                "tests::other",
+                "tests::other"
+            ]
+        );
+        assert_eq!(output[1].2, "boom");
+        assert!(output.iter().all(|(binary, _, _)| binary.is_none()));
+    }
+
+    #[test]
+    fn nextest_output_sections() {
+        let output = captured(&[
+            "--- STDOUT:              my-crate::it m::n ---",
+            "",
+            "running 1 test",
+            "--- STDERR:              my-crate::it m::n ---",
+            "thread 'm::n' panicked at tests/it.rs:5:9:",
+            "        FAIL [   0.003s] my-crate::it m::n",
+        ]);
+        assert_eq!(output.len(), 2);
+        assert_eq!(
+            output[0],
+            (Some("it".to_string()), "m::n".to_string(), String::new())
+        );
+        assert_eq!(output[1].2, "thread 'm::n' panicked at tests/it.rs:5:9:");
+
+        let output = captured(&["  ─── STDOUT: my-crate tests::adds", "printed"]);
+        assert_eq!(
+            output,
+            [(
+                Some("my-crate".to_string()),
+                "tests::adds".to_string(),
+                "printed".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn running_lines() {
+        assert_eq!(
+            running_binary(
+                "     Running unittests src/lib.rs (target/debug/deps/my_crate-1a2b3c4d5e6f7a8b)"
+            )
+            .as_deref(),
+            Some("my_crate")
+        );
+        assert_eq!(
+            running_binary(
+                "\x1b[1m\x1b[92m     Running\x1b[0m tests/it.rs (target/debug/deps/it-0f1e2d3c)"
+            )
+            .as_deref(),
+            Some("it")
+        );
+        assert_eq!(running_binary("test tests::adds ... ok"), None);
+        assert!(same_target("my_crate", "my-crate"));
+    }
+
+    #[test]
+    fn panics() {
+        let (name, location) =
+            parse_panic("thread 'tests::fails' (1234) panicked at src/lib.rs:42:5:").unwrap();
+        assert_eq!(name, "tests::fails");
+        assert_eq!(location.path, PathBuf::from("src/lib.rs"));
+        assert_eq!((location.line, location.column), (42, 5));
+
+        // Before Rust 1.73.
+        let (name, location) =
+            parse_panic("thread 'tests::fails' panicked at 'boom', src/lib.rs:42:5").unwrap();
+        assert_eq!(name, "tests::fails");
+        assert_eq!(location.line, 42);
+    }
+}
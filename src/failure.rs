@@ -0,0 +1,459 @@
+use crate::editor::Location;
+use crate::events::{parse_panic, strip_ansi};
+use regex::Regex;
+use similar::{ChangeTag, TextDiff};
+use std::sync::OnceLock;
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const BOLD: &str = "\x1b[1m";
+const REVERSED: &str = "\x1b[7m";
+const RESET: &str = "\x1b[0m";
+
+/// Values longer than this together are laid out like `{:#?}` for the diff.
+const PRETTY_THRESHOLD: usize = 80;
+
+/// Why a test failed, as found in its captured output.
+pub struct Failure {
+    pub test: String,
+    pub location: Location,
+    /// The panic message, without the `thread '...' panicked at` header.
+    pub message: Vec<String>,
+    pub assertion: Option<Assertion>,
+}
+
+/// The two sides of a failed `assert_eq!` or `assert_ne!`.
+pub struct Assertion {
+    /// Whether the sides were expected to be equal.
+    pub equal: bool,
+    /// The custom message passed to the assertion.
+    pub note: Option<String>,
+    pub left: String,
+    pub right: String,
+    /// Whether the sides are already laid out over lines, as by
+    /// `pretty_assertions`.
+    pub multiline: bool,
+}
+
+/// Finds the first panic in a test's output along with its message, and
+/// the sides of the assertion when it came from `assert_eq!`/`assert_ne!`,
+/// in std's format, its pre-1.73 format or `pretty_assertions`' diff.
+pub fn parse_failure(output: &str) -> Option<Failure> {
+    let lines: Vec<String> = output
+        .lines()
+        .map(|line| strip_ansi(line).to_string())
+        .collect();
+    let (index, (test, location)) = lines
+        .iter()
+        .enumerate()
+        .find_map(|(index, line)| Some((index, parse_panic(line)?)))?;
+
+    let mut message = Vec::new();
+    // Before Rust 1.73 the message was quoted in the header line itself.
+    if let Some((_, quoted)) = lines[index].split_once("panicked at '") {
+        if let Some((text, _)) = quoted.rsplit_once("', ") {
+            message.push(text.to_string());
+        }
+    }
+    for line in &lines[index + 1..] {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("note: ")
+            || trimmed.starts_with("stack backtrace:")
+            || trimmed.starts_with("thread '")
+            || trimmed.starts_with("---- ")
+            || trimmed == "failures:"
+            || trimmed.starts_with("test result:")
+        {
+            break;
+        }
+        message.push(line.clone());
+    }
+    while message.last().is_some_and(|line| line.trim().is_empty()) {
+        message.pop();
+    }
+
+    Some(Failure {
+        assertion: parse_assertion(&message),
+        test,
+        location,
+        message,
+    })
+}
+
+fn parse_assertion(message: &[String]) -> Option<Assertion> {
+    static STD: OnceLock<Regex> = OnceLock::new();
+    static LEGACY: OnceLock<Regex> = OnceLock::new();
+    let std = STD
+        .get_or_init(|| Regex::new(r"^assertion `left (==|!=) right` failed(?:: (.*))?$").unwrap());
+    let legacy = LEGACY.get_or_init(|| {
+        Regex::new(r"^assertion failed: `\(left (==|!=) right\)`(?:: (.*))?$").unwrap()
+    });
+
+    let first = message.first()?;
+    let caps = std.captures(first).or_else(|| legacy.captures(first))?;
+    let equal = &caps[1] == "==";
+    let note = caps.get(2).map(|note| note.as_str().to_string());
+    let rest = &message[1..];
+
+    // pretty_assertions prints a diff instead of the two sides.
+    if let Some(start) = rest
+        .iter()
+        .position(|line| line.starts_with("Diff < left / right >"))
+    {
+        let (mut left, mut right) = (String::new(), String::new());
+        for line in &rest[start + 1..] {
+            let (side, text) = line.split_at(line.chars().next().map_or(0, char::len_utf8));
+            match side {
+                "<" => push_line(&mut left, text),
+                ">" => push_line(&mut right, text),
+                _ => {
+                    push_line(&mut left, text);
+                    push_line(&mut right, text);
+                }
+            }
+        }
+        return Some(Assertion {
+            equal,
+            note,
+            left,
+            right,
+            multiline: true,
+        });
+    }
+    if let Some(start) = rest.iter().position(|line| line == "Both sides:") {
+        let value = rest[start + 1..].join("\n");
+        return Some(Assertion {
+            equal,
+            note,
+            left: value.clone(),
+            right: value,
+            multiline: true,
+        });
+    }
+
+    // `  left: ...` and ` right: ...`, either of which may run over lines.
+    let left_start = rest
+        .iter()
+        .position(|line| line.trim_start().starts_with("left:"))?;
+    let right_start = rest
+        .iter()
+        .position(|line| line.trim_start().starts_with("right:"))?;
+    if right_start <= left_start {
+        return None;
+    }
+    // A side may be empty, as in `  left: ` for an empty string.
+    let side = |lines: &[String], label: &str| {
+        let value = lines.join("\n");
+        let value = value.trim();
+        value
+            .strip_prefix(label)
+            .unwrap_or(value)
+            .trim()
+            .trim_end_matches(',')
+            .to_string()
+    };
+    let left = side(&rest[left_start..right_start], "left:");
+    let mut right = side(&rest[right_start..], "right:");
+    let mut note = note;
+    // The legacy format quotes the sides in backticks and puts the message
+    // after the right one.
+    if right.starts_with('`') {
+        if let Some((value, legacy_note)) = right.clone().split_once("`: ") {
+            note = Some(legacy_note.to_string());
+            right = format!("{}`", value);
+        }
+    }
+    let unquote = |value: &str| {
+        value
+            .strip_prefix('`')
+            .and_then(|value| value.strip_suffix('`'))
+            .unwrap_or(value)
+            .to_string()
+    };
+    Some(Assertion {
+        equal,
+        note,
+        left: unquote(&left),
+        right: unquote(&right),
+        multiline: false,
+    })
+}
+
+fn push_line(side: &mut String, line: &str) {
+    side.push_str(line);
+    side.push('\n');
+}
+
+/// The failure view: the panic message and location, and for assertions a
+/// line diff of the two sides with the changed characters marked.
+pub fn render(failure: &Failure) -> String {
+    let mut text = format!("{}Failure of {}{}\n", BOLD, failure.test, RESET);
+    text.push_str(&format!(
+        "Location: {}:{}:{}\n\n",
+        failure.location.path.display(),
+        failure.location.line,
+        failure.location.column
+    ));
+
+    let Some(assertion) = &failure.assertion else {
+        text.push_str(&format!("{}Message:{}\n", BOLD, RESET));
+        for line in &failure.message {
+            text.push_str(&format!("  {}\n", line));
+        }
+        return text;
+    };
+
+    text.push_str(&format!("{}Message:{}\n", BOLD, RESET));
+    text.push_str(&format!("  {}\n", failure.message[0]));
+    // The legacy format puts the message after the sides.
+    if let Some(note) = assertion
+        .note
+        .as_ref()
+        .filter(|note| !failure.message[0].contains(note.as_str()))
+    {
+        text.push_str(&format!("  {}\n", note));
+    }
+    text.push('\n');
+
+    let (mut left, mut right) = (assertion.left.clone(), assertion.right.clone());
+    if !assertion.multiline && left.len() + right.len() > PRETTY_THRESHOLD {
+        left = pretty_debug(&left);
+        right = pretty_debug(&right);
+    }
+    if !assertion.equal {
+        text.push_str(&format!("{}Both sides are equal:{}\n", BOLD, RESET));
+        for line in left.lines() {
+            text.push_str(&format!("  {}\n", line));
+        }
+        return text;
+    }
+
+    text.push_str(&format!(
+        "{}Diff{} {}- left{} / {}+ right{}:\n",
+        BOLD, RESET, RED, RESET, GREEN, RESET
+    ));
+    for side in [&mut left, &mut right] {
+        if !side.ends_with('\n') {
+            side.push('\n');
+        }
+    }
+    let diff = TextDiff::from_lines(&left, &right);
+    for op in diff.ops() {
+        for change in diff.iter_inline_changes(op) {
+            let (sign, color) = match change.tag() {
+                ChangeTag::Delete => ("-", RED),
+                ChangeTag::Insert => ("+", GREEN),
+                ChangeTag::Equal => (" ", ""),
+            };
+            text.push_str(&format!("{}{} ", color, sign));
+            for (emphasized, value) in change.iter_strings_lossy() {
+                let value = value.trim_end_matches('\n');
+                if emphasized {
+                    text.push_str(&format!("{}{}{}{}", REVERSED, value, RESET, color));
+                } else {
+                    text.push_str(value);
+                }
+            }
+            text.push_str(RESET);
+            text.push('\n');
+        }
+    }
+    text
+}
+
+/// Breaks a one-line `{:?}` dump into the indented layout of `{:#?}` so
+/// that the two sides of an assertion can be compared line by line.
+fn pretty_debug(value: &str) -> String {
+    let newline = |out: &mut String, depth: usize| {
+        out.push('\n');
+        out.push_str(&"    ".repeat(depth));
+    };
+    let mut out = String::new();
+    let mut depth = 0;
+    let mut quote = None;
+    let mut chars = value.trim().chars().peekable();
+    while let Some(c) = chars.next() {
+        if let Some(q) = quote {
+            out.push(c);
+            if c == '\\' {
+                if let Some(escaped) = chars.next() {
+                    out.push(escaped);
+                }
+            } else if c == q {
+                quote = None;
+            }
+            continue;
+        }
+        match c {
+            '"' | '\'' => {
+                quote = Some(c);
+                out.push(c);
+            }
+            '{' | '[' | '(' => {
+                out.push(c);
+                while chars.peek() == Some(&' ') {
+                    chars.next();
+                }
+                if matches!(chars.peek(), Some('}' | ']' | ')')) {
+                    out.extend(chars.next());
+                    continue;
+                }
+                depth += 1;
+                newline(&mut out, depth);
+            }
+            '}' | ']' | ')' => {
+                out.truncate(out.trim_end().len());
+                if !out.ends_with(',') {
+                    out.push(',');
+                }
+                depth = depth.saturating_sub(1);
+                newline(&mut out, depth);
+                out.push(c);
+            }
+            ',' if depth > 0 => {
+                out.push(',');
+                while chars.peek() == Some(&' ') {
+                    chars.next();
+                }
+                newline(&mut out, depth);
+            }
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn sides(message: &[&str]) -> Assertion {
+        let message: Vec<String> = message.iter().map(|line| line.to_string()).collect();
+        parse_assertion(&message).expect("not an assertion")
+    }
+
+    #[test]
+    fn std_assert_eq() {
+        let output = "\
+thread 'tests::std_eq' (28680) panicked at crates/asserts/src/lib.rs:6:9:
+assertion `left == right` failed: values differ
+  left: 1
+ right: 2
+note: run with `RUST_BACKTRACE=1` environment variable to display a backtrace
+";
+        let failure = parse_failure(output).unwrap();
+        assert_eq!(failure.test, "tests::std_eq");
+        assert_eq!(
+            failure.location.path,
+            PathBuf::from("crates/asserts/src/lib.rs")
+        );
+        assert_eq!((failure.location.line, failure.location.column), (6, 9));
+        assert_eq!(failure.message.len(), 3);
+        let assertion = failure.assertion.unwrap();
+        assert!(assertion.equal);
+        assert_eq!(assertion.note.as_deref(), Some("values differ"));
+        assert_eq!(assertion.left, "1");
+        assert_eq!(assertion.right, "2");
+        assert!(!assertion.multiline);
+    }
+
+    #[test]
+    fn multiline_sides() {
+        let assertion = sides(&[
+            "assertion `left == right` failed",
+            "  left: [",
+            "    1,",
+            "]",
+            " right: []",
+        ]);
+        assert_eq!(assertion.left, "[\n    1,\n]");
+        assert_eq!(assertion.right, "[]");
+    }
+
+    #[test]
+    fn empty_sides() {
+        let assertion = sides(&[
+            "assertion `left == right` failed",
+            "  left: ",
+            " right: \"x\"",
+        ]);
+        assert_eq!(assertion.left, "");
+        assert_eq!(assertion.right, "\"x\"");
+
+        let assertion = sides(&["assertion `left == right` failed", "  left: 1", " right:"]);
+        assert_eq!(assertion.left, "1");
+        assert_eq!(assertion.right, "");
+    }
+
+    #[test]
+    fn assert_ne() {
+        let assertion = sides(&["assertion `left != right` failed", "  left: 3", " right: 3"]);
+        assert!(!assertion.equal);
+        assert_eq!(assertion.left, "3");
+    }
+
+    #[test]
+    fn legacy_assert_eq() {
+        let assertion = sides(&[
+            "assertion failed: `(left == right)`",
+            "  left: `1`,",
+            " right: `2`: values differ",
+        ]);
+        assert!(assertion.equal);
+        assert_eq!(assertion.left, "1");
+        assert_eq!(assertion.right, "2");
+        assert_eq!(assertion.note.as_deref(), Some("values differ"));
+    }
+
+    #[test]
+    fn pretty_assertions_diff() {
+        let output = "\
+thread 'tests::pretty_eq' (28681) panicked at crates/asserts/src/lib.rs:12:9:
+assertion failed: `(left == right)`
+
+\x1b[1mDiff\x1b[0m \x1b[31m<\x1b[0m left / \x1b[32mright\x1b[0m \x1b[32m>\x1b[0m :
+ Point {
+\x1b[31m<    x: \x1b[0m\x1b[1;48;5;52;31m1\x1b[0m\x1b[31m,\x1b[0m
+\x1b[32m>    x: \x1b[0m\x1b[1;48;5;22;32m2\x1b[0m\x1b[32m,\x1b[0m
+ }
+
+";
+        let assertion = parse_failure(output).unwrap().assertion.unwrap();
+        assert!(assertion.multiline);
+        assert_eq!(assertion.left, "Point {\n    x: 1,\n}\n");
+        assert_eq!(assertion.right, "Point {\n    x: 2,\n}\n");
+    }
+
+    #[test]
+    fn other_panics() {
+        let output = "thread 'tests::plain' panicked at src/lib.rs:3:5:\nsomething broke\n";
+        let failure = parse_failure(output).unwrap();
+        assert_eq!(failure.message, ["something broke"]);
+        assert!(failure.assertion.is_none());
+        assert!(parse_failure("test tests::plain ... ok\n").is_none());
+    }
+
+    #[test]
+    fn rendered_diff() {
+        let output = "\
+thread 'tests::std_eq' panicked at src/lib.rs:6:9:
+assertion `left == right` failed
+  left: 1
+ right: 2
+";
+        let text = render(&parse_failure(output).unwrap());
+        let text = strip_ansi(&text);
+        assert!(text.contains("Location: src/lib.rs:6:9"));
+        assert!(text.contains("\n- 1\n+ 2\n"));
+    }
+
+    #[test]
+    fn pretty_debug_layout() {
+        assert_eq!(
+            pretty_debug("Point { x: 1, y: [2, 3], z: \"a, b\" }"),
+            "Point {\n    x: 1,\n    y: [\n        2,\n        3,\n    ],\n    z: \"a, b\",\n}"
+        );
+        assert_eq!(pretty_debug("Empty { }"), "Empty {}");
+    }
+}
@@ -1,23 +1,34 @@
+use crate::config::Config;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
 use notify::{Event, RecursiveMode, Result as NotifyResult, Watcher};
 use std::error::Error;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::mpsc::{channel, Receiver};
 
 pub struct FileWatcher {
     watcher: Box<dyn Watcher>,
     rx: Receiver<NotifyResult<Event>>,
+    root: PathBuf,
+    /// Changes that do not trigger a rerun.
+    ignore: Gitignore,
 }
 
 impl FileWatcher {
-    pub fn new() -> Result<Self, Box<dyn Error>> {
+    pub fn new(root: &Path, ignore: &[String]) -> Result<Self, Box<dyn Error>> {
         let (tx, rx) = channel();
         let watcher = notify::recommended_watcher(move |res| {
             tx.send(res).unwrap();
         })?;
+        let mut builder = GitignoreBuilder::new(root);
+        for pattern in ignore {
+            builder.add_line(None, pattern)?;
+        }
 
         Ok(FileWatcher {
             watcher: Box::new(watcher),
             rx,
+            root: root.to_path_buf(),
+            ignore: builder.build()?,
         })
     }
 
@@ -32,14 +43,27 @@ impl FileWatcher {
         }
     }
 
+    /// Whether a change touched a file that is not ignored.
     pub fn should_run_tests(&self, event: &Event) -> bool {
-        // You can customize this logic based on your needs
         matches!(event.kind, notify::EventKind::Modify(_))
+            && event.paths.iter().any(|path| {
+                let Ok(relative) = path.strip_prefix(&self.root) else {
+                    return true;
+                };
+                !self
+                    .ignore
+                    .matched_path_or_any_parents(relative, path.is_dir())
+                    .is_ignore()
+            })
     }
 }
 
-pub fn setup_file_watcher() -> Result<FileWatcher, Box<dyn Error>> {
-    let mut watcher = FileWatcher::new()?;
-    watcher.watch(Path::new("."))?;
+/// Watches the configured paths of the project in `root`.
+pub fn setup_file_watcher(root: &Path, config: &Config) -> Result<FileWatcher, Box<dyn Error>> {
+    let root = root.canonicalize()?;
+    let mut watcher = FileWatcher::new(&root, &config.watch_ignore)?;
+    for path in &config.watch_paths {
+        watcher.watch(&root.join(path))?;
+    }
     Ok(watcher)
 }
@@ -0,0 +1,676 @@
+use crate::app::TestStatus;
+use crate::test_runner::format_duration;
+use chrono::{DateTime, Local};
+use log::warn;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::Duration;
+
+/// How many runs are kept on disk; older ones are deleted.
+const MAX_RUNS: usize = 200;
+
+/// The state of the sources a run was made from.
+#[derive(Clone, PartialEq)]
+pub struct Revision {
+    pub commit: String,
+    /// Whether the working tree had uncommitted changes.
+    pub dirty: bool,
+    /// Hash of the commit and the uncommitted changes; two runs with the same
+    /// fingerprint tested the same sources.
+    pub fingerprint: String,
+}
+
+impl Revision {
+    /// Reads the checked out commit and the changes on top of it, or `None`
+    /// outside a git repository.
+    pub fn current(root: &Path) -> Option<Self> {
+        let git = |args: &[&str]| -> Option<String> {
+            let output = Command::new("git")
+                .current_dir(root)
+                .args(args)
+                .output()
+                .ok()?;
+            output
+                .status
+                .success()
+                .then(|| String::from_utf8_lossy(&output.stdout).to_string())
+        };
+        let commit = git(&["rev-parse", "HEAD"])?.trim().to_string();
+        let diff = git(&["diff", "HEAD"]).unwrap_or_default();
+        let untracked = git(&["ls-files", "--others", "--exclude-standard"]).unwrap_or_default();
+
+        let mut hasher = Fnv::new();
+        hasher.write(commit.as_bytes());
+        hasher.write(diff.as_bytes());
+        for path in untracked.lines() {
+            hasher.write(path.as_bytes());
+            hasher.write(&fs::read(root.join(path)).unwrap_or_default());
+        }
+        Some(Revision {
+            dirty: !diff.is_empty() || !untracked.is_empty(),
+            fingerprint: format!("{:016x}", hasher.0),
+            commit,
+        })
+    }
+
+    /// The abbreviated commit, with a `*` when there were uncommitted changes.
+    pub fn short(&self) -> String {
+        let commit = &self.commit[..self.commit.len().min(8)];
+        if self.dirty {
+            format!("{}*", commit)
+        } else {
+            commit.to_string()
+        }
+    }
+}
+
+/// 64-bit FNV-1a. Unlike `DefaultHasher`, it hashes the same sources to the
+/// same fingerprint with every Rust release, so fingerprints in the history
+/// stay comparable.
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Self {
+        Fnv(0xcbf2_9ce4_8422_2325)
+    }
+
+    /// Hashes the length before the bytes so that fields cannot run into
+    /// each other.
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in (bytes.len() as u64).to_le_bytes().iter().chain(bytes) {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+/// The outcome of one test in a recorded run.
+pub struct TestRecord {
+    pub package: String,
+    pub target: String,
+    pub name: String,
+    pub status: TestStatus,
+    pub duration: Option<Duration>,
+    pub output: String,
+}
+
+/// A finished run as stored in the history.
+pub struct RunRecord {
+    /// File stem of the record, unique and ordered by start time.
+    pub id: String,
+    pub started: DateTime<Local>,
+    pub duration: Duration,
+    pub label: String,
+    /// The commands as they would be typed in a shell.
+    pub commands: Vec<String>,
+    pub revision: Option<Revision>,
+    pub success: bool,
+    pub description: String,
+    pub tests: Vec<TestRecord>,
+    pub output: String,
+}
+
+impl RunRecord {
+    pub fn test(&self, package: &str, target: &str, name: &str) -> Option<&TestRecord> {
+        self.tests
+            .iter()
+            .find(|test| test.package == package && test.target == target && test.name == name)
+    }
+
+    /// Passed, failed (including timed out) and ignored test counts.
+    pub fn counts(&self) -> (usize, usize, usize) {
+        let count = |wanted: &[TestStatus]| {
+            self.tests
+                .iter()
+                .filter(|test| wanted.contains(&test.status))
+                .count()
+        };
+        (
+            count(&[TestStatus::Passed]),
+            count(&[TestStatus::Failed, TestStatus::TimedOut]),
+            count(&[TestStatus::Ignored]),
+        )
+    }
+
+    /// The start time and label, to name the run in lists and titles.
+    pub fn title(&self) -> String {
+        format!(
+            "{} {}",
+            self.started.format("%Y-%m-%d %H:%M:%S"),
+            self.label
+        )
+    }
+
+    /// What the output pane shows for the run: its details, the outcome of
+    /// every test and the output it printed.
+    pub fn render(&self) -> String {
+        let (passed, failed, ignored) = self.counts();
+        let mut text = format!("Run: {}\n", self.label);
+        text.push_str(&format!(
+            "Started: {} (took {})\n",
+            self.started.format("%Y-%m-%d %H:%M:%S"),
+            format_duration(self.duration)
+        ));
+        if let Some(revision) = &self.revision {
+            text.push_str(&format!("Commit: {}\n", revision.short()));
+        }
+        for command in &self.commands {
+            text.push_str(&format!("Command: {}\n", command));
+        }
+        text.push_str(&format!("Status: {}\n", self.description));
+        text.push_str(&format!(
+            "Tests: {} passed, {} failed, {} ignored\n\n",
+            passed, failed, ignored
+        ));
+        for test in &self.tests {
+            text.push_str(&format!(
+                "{:<9} {}{}\n",
+                status_name(test.status),
+                test.name,
+                test.duration
+                    .map(|duration| format!(" <{:.3}s>", duration.as_secs_f64()))
+                    .unwrap_or_default()
+            ));
+        }
+        text.push_str("\n---- output ----\n");
+        text.push_str(&self.output);
+        text
+    }
+
+    /// Lists what changed from `base` to this run: tests whose outcome
+    /// differs, tests only one of them ran, and notable changes in duration.
+    pub fn compare(&self, base: &RunRecord) -> String {
+        let mut text = format!("Comparing {}\n     with {}\n", self.title(), base.title());
+        if let (Some(revision), Some(base_revision)) = (&self.revision, &base.revision) {
+            if revision == base_revision {
+                text.push_str(&format!("Commit: {} (same sources)\n", revision.short()));
+            } else {
+                text.push_str(&format!(
+                    "Commit: {} -> {}\n",
+                    base_revision.short(),
+                    revision.short()
+                ));
+            }
+        }
+        let (passed, failed, ignored) = self.counts();
+        let (base_passed, base_failed, base_ignored) = base.counts();
+        text.push_str(&format!(
+            "Passed: {} -> {}, failed: {} -> {}, ignored: {} -> {}\n",
+            base_passed, passed, base_failed, failed, base_ignored, ignored
+        ));
+
+        let mut changed = Vec::new();
+        let mut durations = Vec::new();
+        let mut added = Vec::new();
+        for test in &self.tests {
+            let Some(before) = base.test(&test.package, &test.target, &test.name) else {
+                added.push(format!("  {} {}", status_name(test.status), test.name));
+                continue;
+            };
+            if before.status != test.status {
+                changed.push(format!(
+                    "  {}: {} -> {}",
+                    test.name,
+                    status_name(before.status),
+                    status_name(test.status)
+                ));
+            }
+            if let (Some(before), Some(after)) = (before.duration, test.duration) {
+                if is_notable_change(before, after) {
+                    durations.push(format!(
+                        "  {}: {:.3}s -> {:.3}s",
+                        test.name,
+                        before.as_secs_f64(),
+                        after.as_secs_f64()
+                    ));
+                }
+            }
+        }
+        let removed: Vec<String> = base
+            .tests
+            .iter()
+            .filter(|test| self.test(&test.package, &test.target, &test.name).is_none())
+            .map(|test| format!("  {} {}", status_name(test.status), test.name))
+            .collect();
+
+        let sections = [
+            ("Changed outcome", changed),
+            ("Duration changed", durations),
+            ("Only in this run", added),
+            ("Only in the earlier run", removed),
+        ];
+        if sections.iter().all(|(_, lines)| lines.is_empty()) {
+            text.push_str("\nNo test changed its outcome or duration.\n");
+        }
+        for (heading, lines) in sections {
+            if lines.is_empty() {
+                continue;
+            }
+            text.push_str(&format!("\n{} ({}):\n", heading, lines.len()));
+            for line in lines {
+                text.push_str(&line);
+                text.push('\n');
+            }
+        }
+        text
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "started": self.started.to_rfc3339(),
+            "duration": self.duration.as_secs_f64(),
+            "label": self.label,
+            "commands": self.commands,
+            "revision": self.revision.as_ref().map(|revision| json!({
+                "commit": revision.commit,
+                "dirty": revision.dirty,
+                "fingerprint": revision.fingerprint,
+            })),
+            "success": self.success,
+            "description": self.description,
+            "tests": self.tests.iter().map(|test| json!({
+                "package": test.package,
+                "target": test.target,
+                "name": test.name,
+                "status": status_name(test.status),
+                "duration": test.duration.map(|duration| duration.as_secs_f64()),
+                "output": test.output,
+            })).collect::<Vec<_>>(),
+            "output": self.output,
+        })
+    }
+
+    fn from_json(id: String, value: &Value) -> Option<Self> {
+        let string = |value: &Value| value.as_str().map(str::to_string);
+        let duration = |value: &Value| value.as_f64().map(Duration::from_secs_f64);
+        let revision = &value["revision"];
+        let tests = value["tests"]
+            .as_array()?
+            .iter()
+            .filter_map(|test| {
+                Some(TestRecord {
+                    package: string(&test["package"])?,
+                    target: string(&test["target"])?,
+                    name: string(&test["name"])?,
+                    status: parse_status(test["status"].as_str()?)?,
+                    duration: duration(&test["duration"]),
+                    output: string(&test["output"]).unwrap_or_default(),
+                })
+            })
+            .collect();
+        Some(RunRecord {
+            id,
+            started: DateTime::parse_from_rfc3339(value["started"].as_str()?)
+                .ok()?
+                .with_timezone(&Local),
+            duration: duration(&value["duration"]).unwrap_or_default(),
+            label: string(&value["label"])?,
+            commands: value["commands"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(string)
+                .collect(),
+            revision: string(&revision["commit"]).map(|commit| Revision {
+                commit,
+                dirty: revision["dirty"] == true,
+                fingerprint: string(&revision["fingerprint"]).unwrap_or_default(),
+            }),
+            success: value["success"] == true,
+            description: string(&value["description"]).unwrap_or_default(),
+            tests,
+            output: string(&value["output"]).unwrap_or_default(),
+        })
+    }
+}
+
+/// Past runs of a workspace, kept as one JSON file per run in
+/// `target/lazytestr/history`.
+pub struct History {
+    dir: PathBuf,
+    /// Oldest first.
+    pub runs: Vec<RunRecord>,
+}
+
+impl History {
+    /// Reads the stored runs, skipping files that cannot be parsed.
+    pub fn load(target_dir: &Path) -> Self {
+        let dir = target_dir.join("lazytestr").join("history");
+        let mut paths: Vec<PathBuf> = fs::read_dir(&dir)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+            .collect();
+        paths.sort();
+
+        let mut runs = Vec::new();
+        for path in paths {
+            let Some(id) = path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+            else {
+                continue;
+            };
+            let record = fs::read(&path)
+                .ok()
+                .and_then(|bytes| serde_json::from_slice::<Value>(&bytes).ok())
+                .and_then(|value| RunRecord::from_json(id, &value));
+            match record {
+                Some(record) => runs.push(record),
+                None => warn!("Skipping unreadable history file {}", path.display()),
+            }
+        }
+        History { dir, runs }
+    }
+
+    /// Stores a run and drops the oldest ones beyond the limit.
+    pub fn save(&mut self, mut run: RunRecord) -> Result<(), Box<dyn Error>> {
+        fs::create_dir_all(&self.dir)?;
+        run.id = format!("{}-{}", run.started.format("%Y%m%d-%H%M%S%3f"), run.id);
+        fs::write(
+            self.dir.join(format!("{}.json", run.id)),
+            serde_json::to_vec(&run.to_json())?,
+        )?;
+        self.runs.push(run);
+
+        let excess = self.runs.len().saturating_sub(MAX_RUNS);
+        for old in self.runs.drain(..excess) {
+            let _ = fs::remove_file(self.dir.join(format!("{}.json", old.id)));
+        }
+        Ok(())
+    }
+}
+
+/// How often a test's outcome flipped between passing and failing from one
+/// run to the next while the sources stayed the same, as a share of those
+/// consecutive runs. Tests that never flipped get no score.
+pub fn flakiness(history: &History) -> HashMap<(String, String, String), f64> {
+    // (fingerprint of the previous outcome, whether it passed) per test
+    let mut previous: HashMap<(&str, &str, &str), (&str, bool)> = HashMap::new();
+    let mut counts: HashMap<(&str, &str, &str), (usize, usize)> = HashMap::new();
+    for run in &history.runs {
+        // Without git there is no telling whether the sources changed.
+        let Some(revision) = &run.revision else {
+            continue;
+        };
+        for test in &run.tests {
+            let passed = match test.status {
+                TestStatus::Passed => true,
+                TestStatus::Failed | TestStatus::TimedOut => false,
+                _ => continue,
+            };
+            let key = (
+                test.package.as_str(),
+                test.target.as_str(),
+                test.name.as_str(),
+            );
+            let fingerprint = revision.fingerprint.as_str();
+            if let Some((before, passed_before)) = previous.insert(key, (fingerprint, passed)) {
+                if before == fingerprint {
+                    let (flips, pairs) = counts.entry(key).or_default();
+                    *pairs += 1;
+                    if passed != passed_before {
+                        *flips += 1;
+                    }
+                }
+            }
+        }
+    }
+    counts
+        .into_iter()
+        .filter(|(_, (flips, _))| *flips > 0)
+        .map(|((package, target, name), (flips, pairs))| {
+            (
+                (package.to_string(), target.to_string(), name.to_string()),
+                flips as f64 / pairs as f64,
+            )
+        })
+        .collect()
+}
+
+/// The median duration of every test over its recorded runs before the
+/// latest one, to tell whether the latest run was unusually slow.
+pub fn baselines(history: &History) -> HashMap<(String, String, String), Duration> {
+    let mut durations: HashMap<(&str, &str, &str), Vec<Duration>> = HashMap::new();
+    let earlier = history.runs.split_last().map_or(&[][..], |(_, runs)| runs);
+    for run in earlier {
+        for test in &run.tests {
+            if let Some(duration) = test.duration {
+                durations
+                    .entry((
+                        test.package.as_str(),
+                        test.target.as_str(),
+                        test.name.as_str(),
+                    ))
+                    .or_default()
+                    .push(duration);
+            }
+        }
+    }
+    durations
+        .into_iter()
+        .filter_map(|((package, target, name), mut durations)| {
+            durations.sort();
+            let median = *durations.get(durations.len() / 2)?;
+            Some((
+                (package.to_string(), target.to_string(), name.to_string()),
+                median,
+            ))
+        })
+        .collect()
+}
+
+/// Whether a duration changed by at least half and by more than a
+/// millisecond, which filters out the jitter of fast tests.
+fn is_notable_change(before: Duration, after: Duration) -> bool {
+    let (before, after) = (before.as_secs_f64(), after.as_secs_f64());
+    (after - before).abs() > 0.001 && (after > before * 1.5 || after < before / 1.5)
+}
+
+fn status_name(status: TestStatus) -> &'static str {
+    match status {
+        TestStatus::Pending => "pending",
+        TestStatus::Running => "running",
+        TestStatus::Passed => "passed",
+        TestStatus::Failed => "failed",
+        TestStatus::Ignored => "ignored",
+        TestStatus::TimedOut => "timed out",
+    }
+}
+
+fn parse_status(name: &str) -> Option<TestStatus> {
+    Some(match name {
+        "pending" => TestStatus::Pending,
+        "running" => TestStatus::Running,
+        "passed" => TestStatus::Passed,
+        "failed" => TestStatus::Failed,
+        "ignored" => TestStatus::Ignored,
+        "timed out" => TestStatus::TimedOut,
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A run as stored on disk, of tests given as (name, status, duration).
+    fn run(id: &str, fingerprint: Option<&str>, tests: &[(&str, &str, Option<f64>)]) -> RunRecord {
+        let value = json!({
+            "started": "2024-05-01T10:00:00+00:00",
+            "duration": 1.5,
+            "label": "workspace",
+            "commands": ["cargo test --workspace --no-fail-fast"],
+            "revision": fingerprint.map(|fingerprint| json!({
+                "commit": "0123456789abcdef0123456789abcdef01234567",
+                "dirty": true,
+                "fingerprint": fingerprint,
+            })),
+            "success": false,
+            "description": "exit status: 101",
+            "tests": tests.iter().map(|(name, status, duration)| json!({
+                "package": "my-crate",
+                "target": "my_crate",
+                "name": name,
+                "status": status,
+                "duration": duration,
+                "output": "",
+            })).collect::<Vec<_>>(),
+            "output": "",
+        });
+        RunRecord::from_json(id.to_string(), &value).unwrap()
+    }
+
+    fn with_runs(runs: Vec<RunRecord>) -> History {
+        History {
+            dir: PathBuf::new(),
+            runs,
+        }
+    }
+
+    fn key(name: &str) -> (String, String, String) {
+        (
+            "my-crate".to_string(),
+            "my_crate".to_string(),
+            name.to_string(),
+        )
+    }
+
+    #[test]
+    fn flips_count_within_a_fingerprint() {
+        let history = with_runs(vec![
+            run(
+                "1",
+                Some("aaaa"),
+                &[("flaky", "passed", None), ("stable", "passed", None)],
+            ),
+            run(
+                "2",
+                Some("aaaa"),
+                &[("flaky", "failed", None), ("stable", "passed", None)],
+            ),
+            run(
+                "3",
+                Some("aaaa"),
+                &[("flaky", "passed", None), ("stable", "passed", None)],
+            ),
+            // The sources changed: the outcome may change without a flip.
+            run(
+                "4",
+                Some("bbbb"),
+                &[("flaky", "failed", None), ("stable", "failed", None)],
+            ),
+            run(
+                "5",
+                Some("bbbb"),
+                &[("flaky", "timed out", None), ("stable", "failed", None)],
+            ),
+        ]);
+        let scores = flakiness(&history);
+        assert_eq!(scores.len(), 1);
+        assert!((scores[&key("flaky")] - 2.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn flips_skip_unknown_sources_and_ignored_tests() {
+        let history = with_runs(vec![
+            run("1", Some("aaaa"), &[("test", "passed", None)]),
+            run("2", None, &[("test", "failed", None)]),
+            run("3", Some("aaaa"), &[("test", "ignored", None)]),
+            run("4", Some("aaaa"), &[("test", "passed", None)]),
+        ]);
+        assert!(flakiness(&history).is_empty());
+
+        let history = with_runs(vec![
+            run("1", Some("aaaa"), &[("test", "passed", None)]),
+            run("2", Some("bbbb"), &[("test", "failed", None)]),
+            run("3", Some("aaaa"), &[("test", "passed", None)]),
+        ]);
+        assert!(flakiness(&history).is_empty());
+    }
+
+    #[test]
+    fn baselines_leave_out_the_latest_run() {
+        let history = with_runs(vec![
+            run(
+                "1",
+                None,
+                &[("slow", "passed", Some(1.0)), ("new", "passed", Some(5.0))],
+            ),
+            run("2", None, &[("slow", "passed", Some(3.0))]),
+            run(
+                "3",
+                None,
+                &[("slow", "passed", Some(2.0)), ("untimed", "passed", None)],
+            ),
+            run(
+                "4",
+                None,
+                &[
+                    ("slow", "passed", Some(10.0)),
+                    ("latest", "passed", Some(4.0)),
+                ],
+            ),
+        ]);
+        let baselines = baselines(&history);
+        assert_eq!(baselines.len(), 2);
+        assert_eq!(baselines[&key("slow")], Duration::from_secs(2));
+        assert_eq!(baselines[&key("new")], Duration::from_secs(5));
+    }
+
+    #[test]
+    fn records_round_trip() {
+        let record = run(
+            "1",
+            Some("aaaa"),
+            &[("adds", "passed", Some(0.25)), ("fails", "failed", None)],
+        );
+        let copy = RunRecord::from_json("1".to_string(), &record.to_json()).unwrap();
+        assert_eq!(copy.label, "workspace");
+        assert_eq!(copy.started, record.started);
+        assert!(copy.revision == record.revision);
+        assert_eq!(copy.counts(), (1, 1, 0));
+        let adds = copy.test("my-crate", "my_crate", "adds").unwrap();
+        assert_eq!(adds.duration, Some(Duration::from_millis(250)));
+        assert_eq!(record.revision.unwrap().short(), "01234567*");
+    }
+
+    #[test]
+    fn comparison() {
+        let base = run(
+            "1",
+            Some("aaaa"),
+            &[("adds", "passed", Some(0.1)), ("gone", "passed", None)],
+        );
+        let latest = run(
+            "2",
+            Some("aaaa"),
+            &[("adds", "failed", Some(0.5)), ("new", "passed", None)],
+        );
+        let text = latest.compare(&base);
+        assert!(text.contains("Commit: 01234567* (same sources)"));
+        assert!(text.contains("Changed outcome (1):\n  adds: passed -> failed\n"));
+        assert!(text.contains("Duration changed (1):\n  adds: 0.100s -> 0.500s\n"));
+        assert!(text.contains("Only in this run (1):\n  passed new\n"));
+        assert!(text.contains("Only in the earlier run (1):\n  passed gone\n"));
+    }
+
+    #[test]
+    fn fingerprints_are_stable() {
+        // Stored fingerprints must match those of later builds.
+        let mut hasher = Fnv::new();
+        hasher.write(b"0123456789abcdef0123456789abcdef01234567");
+        hasher.write(b"");
+        assert_eq!(format!("{:016x}", hasher.0), "d6cef38d7678ba15");
+
+        let mut other = Fnv::new();
+        other.write(b"0123456789abcdef0123456789abcdef0123456");
+        other.write(b"7");
+        assert_ne!(other.0, hasher.0);
+    }
+}
@@ -1,13 +1,25 @@
 mod app;
+mod backtrace;
+mod cli;
 mod config;
+mod editor;
+mod events;
+mod failure;
 mod file_watcher;
+mod history;
+mod test_list;
 mod test_runner;
+mod tree;
 mod ui;
 mod utils;
+mod workspace;
 
-use app::App;
-use config::setup_environment;
+use app::{Action, App};
+use clap::Parser;
+use cli::{Cli, CliCommand};
+use config::{setup_environment, Config};
 use crossterm::event::{self, Event};
+use editor::open_in_editor;
 use file_watcher::setup_file_watcher;
 use log::debug;
 use std::error::Error;
@@ -16,58 +28,131 @@ use test_runner::TestRunner;
 use ui::{draw, restore_terminal, setup_terminal};
 
 fn main() -> Result<(), Box<dyn Error>> {
-    setup_environment()?;
+    let cli = Cli::parse();
+    let root = cli.path.as_path();
+    let mut config = Config::load(root, cli.config.as_deref());
+    cli.apply(&mut config);
+    setup_environment(&config, &cli.log_file)?;
+
+    // Without the UI, config errors go to stderr.
+    if cli.command.is_some() {
+        for error in &config.errors {
+            eprintln!("Config error: {}", error);
+        }
+    }
+    match cli.command {
+        Some(CliCommand::List) => return cli::list(&cli),
+        Some(CliCommand::Run) => {
+            let passed = cli::run(&cli, &config)?;
+            std::process::exit(if passed { 0 } else { 1 });
+        }
+        None => {}
+    }
 
     let mut terminal = setup_terminal()?;
-    let mut app = App::new();
+    let mut app = App::new(&config);
+    for error in &config.errors {
+        app.show_message(&format!("Config error: {}", error));
+    }
+    app.package = cli.package.clone();
+    app.watch_mode = cli.watch;
+    if let Some(filter) = &cli.filter {
+        app.set_filter(filter.clone());
+    }
     let (output_tx, output_rx) = channel();
-    let test_runner = TestRunner::new(output_tx);
-    let file_watcher = setup_file_watcher()?;
+    let (scan_tx, scan_rx) = channel();
+    let mut test_runner = TestRunner::new(output_tx, scan_tx, &config);
+    config
+        .watch_ignore
+        .extend(cli::log_file_pattern(root, &cli.log_file));
+    let file_watcher = setup_file_watcher(root, &config)?;
 
     // Initial scan for tests
-    test_runner.scan_for_tests(&mut app, ".")?;
+    test_runner.scan_for_tests(app.discovery_mode, root)?;
 
     loop {
         let height = terminal.size()?.height as usize;
         app.update_output_height(height - 2);
         // Handle test output
-        while let Ok(output) = output_rx.try_recv() {
-            app.add_test_output(&output);
+        while let Ok(event) = output_rx.try_recv() {
+            app.handle_runner_event(event);
             let height = terminal.size()?.height as usize;
             app.update_output_height(height - 2);
             app.update_scroll();
         }
 
+        // Handle finished test discovery
+        while let Ok((workspace, test_info)) = scan_rx.try_recv() {
+            debug!("Rescan complete. Found {} test files", test_info.len());
+            app.set_test_info(workspace, test_info);
+        }
+
         // Handle input
         if event::poll(std::time::Duration::from_millis(100))? {
             if let Event::Key(key) = event::read()? {
-                let should_run_action = app.handle_input(key.code)?;
+                let action = app.handle_input(key.code)?;
                 if app.should_quit {
                     break;
                 }
-                if should_run_action {
-                    if app.active_pane == 1 {
-                        debug!("Running selected test");
-                        app.clear_test_output();
-                        test_runner.run_test(&app)?;
-                    } else if app.active_pane == 0 {
+                match action {
+                    Action::Run(specs) => {
+                        if let Some(workspace) = &app.workspace {
+                            debug!("Running {} spec(s)", specs.len());
+                            test_runner.enqueue(&workspace.root, &specs);
+                            app.last_run = specs;
+                        }
+                    }
+                    Action::Stress(spec) => {
+                        if let Some(workspace) = &app.workspace {
+                            test_runner.enqueue_stress(&workspace.root, spec);
+                        }
+                    }
+                    Action::SetBacktrace(backtrace) => test_runner.backtrace = backtrace,
+                    Action::CancelAll => {
+                        debug!("Cancelling all jobs");
+                        test_runner.cancel_all();
+                    }
+                    Action::CancelJob(id) => test_runner.cancel(id),
+                    Action::RemoveJob(id) => test_runner.remove(id),
+                    Action::OpenEditor(location) => {
+                        debug!(
+                            "Opening {}:{} in the editor",
+                            location.path.display(),
+                            location.line
+                        );
+                        if let Err(err) =
+                            open_in_editor(&mut terminal, &location, config.editor.as_deref())
+                        {
+                            app.show_message(&format!("Failed to open the editor: {}", err));
+                        }
+                    }
+                    Action::Rescan => {
                         debug!("Rescanning for tests");
-                        test_runner.scan_for_tests(&mut app, ".")?;
-                        debug!("Rescan complete. Found {} test files", app.test_info.len());
+                        test_runner.scan_for_tests(app.discovery_mode, root)?;
                     }
+                    Action::None => {}
                 }
             }
         }
 
+        test_runner.update();
+        app.set_jobs(test_runner.jobs());
+
         terminal.draw(|f| draw(f, &mut app))?;
         // let should_run_action = app.handle_input()?;
 
-        // Handle file watcher events
-        if app.watch_mode {
-            if let Some(event) = file_watcher.check_events() {
-                if file_watcher.should_run_tests(&event) {
-                    debug!("File change detected, running tests");
-                    test_runner.run_test(&app)?;
+        // Handle file watcher events. All pending events are taken at once,
+        // so builds writing to ignored paths do not hold up real changes.
+        let mut changed = false;
+        while let Some(event) = file_watcher.check_events() {
+            changed |= file_watcher.should_run_tests(&event);
+        }
+        if changed && app.watch_mode {
+            if let Some(workspace) = &app.workspace {
+                if !app.last_run.is_empty() {
+                    debug!("File change detected, rerunning last run");
+                    let specs = app.last_run.clone();
+                    test_runner.enqueue(&workspace.root, &specs);
                 }
             }
         }
@@ -0,0 +1,265 @@
+use crate::app::{TestCase, TestInfo, TestStatus};
+use crate::events::RunnerEvent;
+use crate::utils::scan_crate;
+use crate::workspace::{Package, Target, Workspace};
+use serde_json::Value;
+use std::collections::HashSet;
+use std::error::Error;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::mpsc::Sender;
+use std::thread;
+
+/// A test executable built by `cargo test --no-run`.
+struct TestBinary {
+    executable: PathBuf,
+    src_path: PathBuf,
+}
+
+/// The tests of one test binary, keyed by the crate root it was built from.
+struct ListedSuite {
+    src_path: PathBuf,
+    tests: Vec<ListedTest>,
+}
+
+/// A test as reported by the test binary itself.
+struct ListedTest {
+    name: String,
+    ignored: bool,
+}
+
+/// Asks the compiled test binaries for the tests they contain, which includes
+/// tests generated by macros that a source scan cannot see.
+pub fn list_tests(
+    workspace: &Workspace,
+    use_nextest: bool,
+    build_args: &[String],
+    output_tx: &Sender<RunnerEvent>,
+) -> Result<Vec<TestInfo>, Box<dyn Error>> {
+    let binaries = build_test_binaries(&workspace.root, build_args, output_tx)?;
+
+    let listed = if use_nextest {
+        list_with_nextest(&workspace.root, &binaries, build_args)?
+    } else {
+        let mut listed = Vec::new();
+        for binary in &binaries {
+            listed.push(ListedSuite {
+                src_path: binary.src_path.clone(),
+                tests: list_with_libtest(binary)?,
+            });
+        }
+        listed
+    };
+
+    let mut test_info = Vec::new();
+    for suite in listed {
+        if let Some((package, target)) = workspace.find_target(&suite.src_path) {
+            test_info.extend(assign_to_files(package, target, suite.tests)?);
+        }
+    }
+    Ok(test_info)
+}
+
+fn build_test_binaries(
+    dir: &Path,
+    build_args: &[String],
+    output_tx: &Sender<RunnerEvent>,
+) -> Result<Vec<TestBinary>, Box<dyn Error>> {
+    let mut cmd = Command::new("cargo")
+        .current_dir(dir)
+        .env("CARGO_TERM_COLOR", "always")
+        .args([
+            "test",
+            "--workspace",
+            "--no-run",
+            "--message-format",
+            "json",
+        ])
+        .args(build_args)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    let stderr = cmd.stderr.take().unwrap();
+    let tx = output_tx.clone();
+    thread::spawn(move || {
+        let reader = BufReader::new(stderr);
+        for line in reader.lines().map_while(Result::ok) {
+            let _ = tx.send(RunnerEvent::Output(line));
+        }
+    });
+
+    let mut binaries = Vec::new();
+    let reader = BufReader::new(cmd.stdout.take().unwrap());
+    for line in reader.lines().map_while(Result::ok) {
+        let Ok(message) = serde_json::from_str::<Value>(&line) else {
+            continue;
+        };
+        if message["reason"] != "compiler-artifact" || message["profile"]["test"] != true {
+            continue;
+        }
+        if let (Some(executable), Some(src_path)) = (
+            message["executable"].as_str(),
+            message["target"]["src_path"].as_str(),
+        ) {
+            binaries.push(TestBinary {
+                executable: PathBuf::from(executable),
+                src_path: PathBuf::from(src_path),
+            });
+        }
+    }
+
+    let status = cmd.wait()?;
+    if !status.success() {
+        return Err(format!("cargo test --no-run failed with status: {}", status).into());
+    }
+    Ok(binaries)
+}
+
+fn list_with_libtest(binary: &TestBinary) -> Result<Vec<ListedTest>, Box<dyn Error>> {
+    let list = |extra: &[&str]| -> Result<Vec<String>, Box<dyn Error>> {
+        let output = Command::new(&binary.executable)
+            .args(["--list", "--format", "terse"])
+            .args(extra)
+            .output()?;
+        if !output.status.success() {
+            return Err(format!(
+                "{} --list failed with status: {}\n{}",
+                binary.executable.display(),
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim_end()
+            )
+            .into());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter_map(|line| line.strip_suffix(": test"))
+            .map(str::to_string)
+            .collect())
+    };
+
+    let ignored: HashSet<String> = list(&["--ignored"])?.into_iter().collect();
+    Ok(list(&[])?
+        .into_iter()
+        .map(|name| ListedTest {
+            ignored: ignored.contains(&name),
+            name,
+        })
+        .collect())
+}
+
+/// Lists the tests with nextest. `build_args` must be those the binaries were
+/// built with, `--target-dir` included: suites are matched to the binaries by
+/// path, and nextest would otherwise build its own.
+fn list_with_nextest(
+    dir: &Path,
+    binaries: &[TestBinary],
+    build_args: &[String],
+) -> Result<Vec<ListedSuite>, Box<dyn Error>> {
+    let output = Command::new("cargo")
+        .current_dir(dir)
+        .args(["nextest", "list", "--workspace", "--message-format", "json"])
+        .args(build_args)
+        .stderr(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Err(format!("cargo nextest list failed with status: {}", output.status).into());
+    }
+
+    let list: Value = serde_json::from_slice(&output.stdout)?;
+    let mut listed = Vec::new();
+    if let Some(suites) = list["rust-suites"].as_object() {
+        for suite in suites.values() {
+            let Some(binary_path) = suite["binary-path"].as_str() else {
+                continue;
+            };
+            let Some(binary) = binaries
+                .iter()
+                .find(|binary| binary.executable == Path::new(binary_path))
+            else {
+                continue;
+            };
+
+            let mut tests = Vec::new();
+            if let Some(testcases) = suite["testcases"].as_object() {
+                for (name, testcase) in testcases {
+                    // Tests excluded by a nextest default-filter won't run.
+                    if testcase["filter-match"]["status"] == "mismatch" {
+                        continue;
+                    }
+                    tests.push(ListedTest {
+                        name: name.clone(),
+                        ignored: testcase["ignored"] == true,
+                    });
+                }
+            }
+            listed.push(ListedSuite {
+                src_path: binary.src_path.clone(),
+                tests,
+            });
+        }
+    }
+    Ok(listed)
+}
+
+/// Places each listed test in the source file of the innermost module that
+/// contains it, reusing the attributes found by the source scan.
+fn assign_to_files(
+    package: &Package,
+    target: &Target,
+    tests: Vec<ListedTest>,
+) -> Result<Vec<TestInfo>, Box<dyn Error>> {
+    let files = scan_crate(&target.src_path)?;
+    let new_info = |path: &Path| TestInfo {
+        path: path.to_path_buf(),
+        package: package.name.clone(),
+        target: target.clone(),
+        tests: Vec::new(),
+    };
+    let mut test_info: Vec<TestInfo> = files.iter().map(|file| new_info(&file.path)).collect();
+    if test_info.is_empty() {
+        test_info.push(new_info(&target.src_path));
+    }
+
+    for test in tests {
+        let mut segments: Vec<&str> = test.name.split("::").collect();
+        segments.pop();
+
+        let index = files
+            .iter()
+            .enumerate()
+            .filter(|(_, file)| {
+                file.module_path.len() <= segments.len()
+                    && file.module_path.iter().zip(&segments).all(|(a, b)| a == b)
+            })
+            .max_by_key(|(_, file)| file.module_path.len())
+            .map_or(0, |(index, _)| index);
+
+        let scanned = files
+            .get(index)
+            .and_then(|file| file.tests.iter().find(|scanned| scanned.name == test.name));
+        let mut attributes = scanned
+            .map(|scanned| scanned.attributes.clone())
+            .unwrap_or_default();
+        if test.ignored && !attributes.iter().any(|attr| attr == "ignore") {
+            attributes.push("ignore".to_string());
+        }
+
+        test_info[index].tests.push(TestCase {
+            name: test.name,
+            attributes,
+            status: TestStatus::Pending,
+            duration: None,
+            location: scanned.and_then(|scanned| scanned.location),
+            panic: None,
+            output: String::new(),
+            flakiness: None,
+            stress: None,
+            baseline: None,
+        });
+    }
+
+    test_info.retain(|info| !info.tests.is_empty());
+    Ok(test_info)
+}
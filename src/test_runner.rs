@@ -1,111 +1,826 @@
-use crate::app::{App, TestInfo};
+use crate::app::{DiscoveryMode, TestInfo, TestStatus};
+use crate::config::{Config, Runner};
+use crate::events::{running_binary, LineParser, RunnerEvent, TestResult};
+use crate::history::Revision;
+use crate::test_list::list_tests;
 use crate::utils::scan_for_tests;
+use crate::workspace::{Target, Workspace};
+use std::collections::HashMap;
+use std::env;
 use std::error::Error;
+use std::fmt;
+use std::io;
 use std::io::{BufRead, BufReader};
-use std::process::{Command, Stdio};
-use std::sync::mpsc::Sender;
-use std::thread;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+const KILL_GRACE_PERIOD: Duration = Duration::from_secs(2);
+const DIAGNOSTIC_FORMAT: &str = "json-diagnostic-rendered-ansi";
+
+/// The `RUST_BACKTRACE` setting tests run with.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Backtrace {
+    Off,
+    Short,
+    Full,
+}
+
+impl Backtrace {
+    /// The setting of the environment lazytestr was started in.
+    pub fn from_env() -> Self {
+        match env::var("RUST_BACKTRACE").as_deref() {
+            Ok("full") => Backtrace::Full,
+            Ok("0") | Err(_) => Backtrace::Off,
+            Ok(_) => Backtrace::Short,
+        }
+    }
+
+    pub fn next(self) -> Self {
+        match self {
+            Backtrace::Off => Backtrace::Short,
+            Backtrace::Short => Backtrace::Full,
+            Backtrace::Full => Backtrace::Off,
+        }
+    }
+}
+
+impl fmt::Display for Backtrace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Backtrace::Off => write!(f, "0"),
+            Backtrace::Short => write!(f, "1"),
+            Backtrace::Full => write!(f, "full"),
+        }
+    }
+}
+
+/// A set of tests to run with a single cargo invocation.
+#[derive(Clone, PartialEq)]
+pub struct RunSpec {
+    pub label: String,
+    /// Runs the whole workspace when unset.
+    pub package: Option<String>,
+    /// Runs every target of the package when unset.
+    pub target: Option<Target>,
+    /// Test name filters; a test runs if it matches any of them.
+    pub filters: Vec<String>,
+    /// Whether `filters` are full test names rather than substrings.
+    pub exact: bool,
+}
+
+impl RunSpec {
+    pub fn workspace() -> Self {
+        RunSpec {
+            label: "workspace".to_string(),
+            package: None,
+            target: None,
+            filters: Vec::new(),
+            exact: false,
+        }
+    }
+
+    pub fn package(package: &str) -> Self {
+        RunSpec {
+            label: package.to_string(),
+            package: Some(package.to_string()),
+            ..RunSpec::workspace()
+        }
+    }
+
+    /// Arguments for cargo and for the test harness (after `--`).
+    fn args(&self) -> (Vec<String>, Vec<String>) {
+        let mut cargo_args = Vec::new();
+        match &self.package {
+            Some(package) => {
+                cargo_args.push("-p".to_string());
+                cargo_args.push(package.clone());
+            }
+            None => cargo_args.push("--workspace".to_string()),
+        }
+        if let Some(target) = &self.target {
+            cargo_args.extend(target.cargo_args());
+        }
+        // Keep going after a failing test binary so every test gets a status.
+        cargo_args.push("--no-fail-fast".to_string());
+
+        // Both libtest and nextest accept any number of filters after `--`.
+        let mut test_args = Vec::new();
+        if self.exact {
+            test_args.push("--exact".to_string());
+        }
+        test_args.extend(self.filters.iter().cloned());
+        (cargo_args, test_args)
+    }
+
+    pub fn includes_target(&self, package: &str, target: &Target) -> bool {
+        self.package.as_ref().is_none_or(|p| p == package)
+            && self.target.as_ref().is_none_or(|t| t == target)
+    }
+
+    pub fn includes_test(&self, name: &str) -> bool {
+        self.filters.is_empty()
+            || self.filters.iter().any(|filter| {
+                if self.exact {
+                    name == filter
+                } else {
+                    name.contains(filter.as_str())
+                }
+            })
+    }
+}
 
 pub struct TestRunner {
-    output_tx: Sender<String>,
+    output_tx: Sender<RunnerEvent>,
+    scan_tx: Sender<(Workspace, Vec<TestInfo>)>,
     use_nextest: bool,
+    /// Ask the harness for structured JSON results. libtest only offers this
+    /// on nightly, so it is opted into with `libtest-json` and enabled on
+    /// stable through `RUSTC_BOOTSTRAP`.
+    libtest_json: bool,
+    /// Features and extra arguments for every cargo invocation.
+    build_args: Vec<String>,
+    /// How long a single test may run before it is killed. Under nextest
+    /// only tests that report their start can be timed, which takes
+    /// `libtest_json`.
+    test_timeout: Option<Duration>,
+    /// How long a whole run may take.
+    run_timeout: Option<Duration>,
+    /// How many jobs may run at once. Jobs only run side by side when they
+    /// test different packages.
+    concurrency: usize,
+    /// How many times a stress run repeats a test.
+    stress_runs: usize,
+    /// What new jobs set `RUST_BACKTRACE` to.
+    pub backtrace: Backtrace,
+    jobs: Vec<Job>,
+    next_job_id: usize,
+    active: HashMap<usize, ActiveRun>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum JobState {
+    Queued,
+    Running,
+    Passed,
+    Failed,
+    Cancelled,
+    TimedOut,
 }
 
+/// A queued or finished request to run tests.
+#[derive(Clone)]
+pub struct Job {
+    pub id: usize,
+    pub label: String,
+    pub root: PathBuf,
+    pub specs: Vec<RunSpec>,
+    /// How many times the specs run in a row; more than once for stress runs.
+    pub repeat: usize,
+    pub backtrace: Backtrace,
+    pub state: JobState,
+    pub started: Option<Instant>,
+    pub finished: Option<Instant>,
+}
+
+impl Job {
+    pub fn is_done(&self) -> bool {
+        !matches!(self.state, JobState::Queued | JobState::Running)
+    }
+
+    pub fn elapsed(&self) -> Option<Duration> {
+        let started = self.started?;
+        Some(self.finished.unwrap_or_else(Instant::now) - started)
+    }
+
+    /// Packages the job tests, or `None` for the whole workspace.
+    fn packages(&self) -> Option<Vec<&str>> {
+        self.specs
+            .iter()
+            .map(|spec| spec.package.as_deref())
+            .collect()
+    }
+
+    fn conflicts_with(&self, other: &Job) -> bool {
+        match (self.packages(), other.packages()) {
+            (Some(ours), Some(theirs)) => ours.iter().any(|package| theirs.contains(package)),
+            _ => true,
+        }
+    }
+}
+
+/// Handle to a job in progress; the thread returns how the job ended.
+struct ActiveRun {
+    cancelled: Arc<AtomicBool>,
+    handle: JoinHandle<JobState>,
+}
+
+/// Finished jobs kept in the job list.
+const MAX_FINISHED_JOBS: usize = 20;
+
 impl TestRunner {
-    pub fn new(output_tx: Sender<String>) -> Self {
-        let use_nextest = Self::check_nextest_installed();
+    pub fn new(
+        output_tx: Sender<RunnerEvent>,
+        scan_tx: Sender<(Workspace, Vec<TestInfo>)>,
+        config: &Config,
+    ) -> Self {
+        let use_nextest = match config.runner {
+            Runner::Auto => Self::check_nextest_installed(),
+            Runner::Cargo => false,
+            Runner::Nextest => {
+                let installed = Self::check_nextest_installed();
+                if !installed {
+                    let _ = output_tx.send(RunnerEvent::Output(
+                        "cargo-nextest is not installed; running tests with cargo test".to_string(),
+                    ));
+                }
+                installed
+            }
+        };
+        if use_nextest && config.test_timeout.is_some() && !config.libtest_json {
+            let _ = output_tx.send(RunnerEvent::Output(
+                "The test timeout is ignored under nextest without libtest-json; \
+                 set a slow-timeout in .config/nextest.toml instead"
+                    .to_string(),
+            ));
+        }
         TestRunner {
             output_tx,
+            scan_tx,
             use_nextest,
+            libtest_json: config.libtest_json,
+            build_args: config.build_args(),
+            test_timeout: config.test_timeout,
+            run_timeout: config.run_timeout,
+            concurrency: config.jobs,
+            stress_runs: config.stress_runs,
+            backtrace: Backtrace::from_env(),
+            jobs: Vec::new(),
+            next_job_id: 1,
+            active: HashMap::new(),
         }
     }
 
     fn check_nextest_installed() -> bool {
         Command::new("cargo")
-            .args(&["nextest", "--version"])
+            .args(["nextest", "--version"])
             .output()
             .map(|output| output.status.success())
             .unwrap_or(false)
     }
-    pub fn run_test(&self, app: &App) -> Result<(), Box<dyn Error>> {
-        if let Some(info) = app.test_info.get(app.selected_index) {
-            if let Some(test_name) = info.tests.get(app.selected_test) {
-                let test_name = test_name.clone();
-                let tx = self.output_tx.clone();
-                let path = info.path.clone();
-
-                let use_nextest = self.use_nextest;
-                thread::spawn(move || {
-                    tx.send(format!("Running test: {}\n", test_name)).unwrap();
-
-                    let mut cmd = if use_nextest {
-                        Command::new("cargo")
-                            .current_dir(path.parent().unwrap())
-                            .args(&["nextest", "run", &test_name, "--no-capture"])
-                            .stdout(Stdio::piped())
-                            .stderr(Stdio::piped())
-                            .spawn()
-                            .expect("Failed to start nextest command")
-                    } else {
-                        Command::new("cargo")
-                            .current_dir(path.parent().unwrap())
-                            .args(&["test", &test_name, "--", "--nocapture"])
-                            .stdout(Stdio::piped())
-                            .stderr(Stdio::piped())
-                            .spawn()
-                            .expect("Failed to start test command")
-                    };
-
-                    let stdout = cmd.stdout.take().unwrap();
-                    let stderr = cmd.stderr.take().unwrap();
-
-                    let tx_clone = tx.clone();
-                    thread::spawn(move || {
-                        let reader = BufReader::new(stdout);
-                        for line in reader.lines() {
-                            if let Ok(line) = line {
-                                tx_clone.send(line + "\n").unwrap();
-                            }
-                        }
-                    });
-
-                    let tx_clone = tx.clone();
-                    thread::spawn(move || {
-                        let reader = BufReader::new(stderr);
-                        for line in reader.lines() {
-                            if let Ok(line) = line {
-                                tx_clone.send(line + "\n").unwrap();
-                            }
-                        }
-                    });
-
-                    let status = cmd.wait().expect("Failed to wait on child");
-                    tx.send(format!("Test finished with status: {:?}\n", status))
-                        .unwrap();
-                });
+
+    pub fn jobs(&self) -> &[Job] {
+        &self.jobs
+    }
+
+    /// Adds a run to the queue, unless the same run is already waiting.
+    pub fn enqueue(&mut self, root: &Path, specs: &[RunSpec]) {
+        let queued = self
+            .jobs
+            .iter()
+            .any(|job| job.state == JobState::Queued && job.repeat == 1 && job.specs == specs);
+        if queued {
+            return;
+        }
+        let label = specs
+            .iter()
+            .map(|spec| spec.label.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        self.push_job(label, root, specs.to_vec(), 1);
+    }
+
+    /// Queues a run that repeats the spec to see how reliably it passes.
+    pub fn enqueue_stress(&mut self, root: &Path, spec: RunSpec) {
+        let label = format!("stress {} x{}", spec.label, self.stress_runs);
+        self.push_job(label, root, vec![spec], self.stress_runs);
+    }
+
+    fn push_job(&mut self, label: String, root: &Path, specs: Vec<RunSpec>, repeat: usize) {
+        self.jobs.push(Job {
+            id: self.next_job_id,
+            label,
+            root: root.to_path_buf(),
+            specs,
+            repeat,
+            backtrace: self.backtrace,
+            state: JobState::Queued,
+            started: None,
+            finished: None,
+        });
+        self.next_job_id += 1;
+        self.update();
+    }
+
+    /// Stops a job: a queued job never starts, a running one is killed.
+    pub fn cancel(&mut self, id: usize) {
+        if let Some(active) = self.active.get(&id) {
+            active.cancelled.store(true, Ordering::SeqCst);
+        } else if let Some(job) = self
+            .jobs
+            .iter_mut()
+            .find(|job| job.id == id && job.state == JobState::Queued)
+        {
+            job.state = JobState::Cancelled;
+            job.finished = Some(Instant::now());
+        }
+    }
+
+    /// Cancels every job that has not finished yet.
+    pub fn cancel_all(&mut self) {
+        let ids: Vec<usize> = self
+            .jobs
+            .iter()
+            .filter(|job| !job.is_done())
+            .map(|job| job.id)
+            .collect();
+        for id in ids {
+            self.cancel(id);
+        }
+    }
+
+    /// Drops a job from the list. A running job is cancelled instead and
+    /// stays listed until it has stopped.
+    pub fn remove(&mut self, id: usize) {
+        if self.active.contains_key(&id) {
+            self.cancel(id);
+        } else {
+            self.jobs.retain(|job| job.id != id);
+        }
+    }
+
+    /// Collects finished jobs and starts queued ones while there is room.
+    pub fn update(&mut self) {
+        let finished: Vec<usize> = self
+            .active
+            .iter()
+            .filter(|(_, active)| active.handle.is_finished())
+            .map(|(id, _)| *id)
+            .collect();
+        for id in finished {
+            let active = self.active.remove(&id).unwrap();
+            let state = active.handle.join().unwrap_or(JobState::Failed);
+            if let Some(job) = self.jobs.iter_mut().find(|job| job.id == id) {
+                job.state = state;
+                job.finished = Some(Instant::now());
             }
         }
-        Ok(())
+
+        // Jobs start in order; a job may only overtake earlier queued jobs
+        // when it tests none of their packages.
+        let mut blocked: Vec<usize> = Vec::new();
+        for index in 0..self.jobs.len() {
+            if self.active.len() >= self.concurrency {
+                break;
+            }
+            let job = &self.jobs[index];
+            if job.state != JobState::Queued {
+                continue;
+            }
+            let conflict = self
+                .jobs
+                .iter()
+                .filter(|other| other.state == JobState::Running || blocked.contains(&other.id))
+                .any(|other| other.conflicts_with(job));
+            if conflict {
+                blocked.push(job.id);
+                continue;
+            }
+            self.start(index);
+        }
+
+        let done = self.jobs.iter().filter(|job| job.is_done()).count();
+        let mut excess = done.saturating_sub(MAX_FINISHED_JOBS);
+        self.jobs.retain(|job| {
+            let drop = excess > 0 && job.is_done();
+            if drop {
+                excess -= 1;
+            }
+            !drop
+        });
     }
 
-    pub fn scan_for_tests(&self, app: &mut App, dir: &str) -> Result<(), Box<dyn Error>> {
-        self.output_tx
-            .send("Rescanning for tests...\n".to_string())?;
+    /// Runs the specs of a job one after another in the background.
+    fn start(&mut self, index: usize) {
+        let job = &mut self.jobs[index];
+        job.state = JobState::Running;
+        job.started = Some(Instant::now());
+        let id = job.id;
+        let specs = job.specs.clone();
+        let root = job.root.clone();
+
+        let repeat = job.repeat;
+        let backtrace = job.backtrace;
+
+        let tx = self.output_tx.clone();
+        let command_lines = specs
+            .iter()
+            .map(|spec| command_line(&self.command(&root, spec, backtrace)))
+            .collect();
+        let mut commands: Vec<(String, Command)> = Vec::new();
+        for round in 1..=repeat {
+            for spec in &specs {
+                let label = if repeat > 1 {
+                    format!("{} ({}/{})", spec.label, round, repeat)
+                } else {
+                    spec.label.clone()
+                };
+                commands.push((label, self.command(&root, spec, backtrace)));
+            }
+        }
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let run_cancelled = cancelled.clone();
+        let test_timeout = self.test_timeout;
+        let run_timeout = self.run_timeout;
+
+        let handle = thread::spawn(move || {
+            let send = |event: RunnerEvent| {
+                let _ = tx.send(event.for_job(id));
+            };
+            send(RunnerEvent::RunStarted {
+                specs,
+                repeat,
+                commands: command_lines,
+                revision: Revision::current(&root),
+            });
+
+            let deadline = run_timeout.map(|timeout| Instant::now() + timeout);
+            let timed_out = || deadline.is_some_and(|deadline| Instant::now() >= deadline);
+            let mut statuses = Vec::new();
+            for (label, mut command) in commands {
+                if run_cancelled.load(Ordering::SeqCst) || timed_out() {
+                    break;
+                }
+                send(RunnerEvent::Output(format!("Running: {}", label)));
+                let watchdog = Watchdog::new(test_timeout, deadline);
+                match Self::run_command(&send, &mut command, &run_cancelled, watchdog) {
+                    Ok(status) => statuses.push(status),
+                    Err(err) => {
+                        send(RunnerEvent::Output(format!("Failed to run cargo: {}", err)));
+                        send(RunnerEvent::RunFinished {
+                            success: false,
+                            description: err.to_string(),
+                        });
+                        return JobState::Failed;
+                    }
+                }
+            }
+
+            let (state, description) = if run_cancelled.load(Ordering::SeqCst) {
+                (JobState::Cancelled, "cancelled".to_string())
+            } else if timed_out() {
+                (
+                    JobState::TimedOut,
+                    format!(
+                        "timed out after {}",
+                        format_duration(run_timeout.unwrap_or_default())
+                    ),
+                )
+            } else {
+                let state = if statuses.iter().all(ExitStatus::success) {
+                    JobState::Passed
+                } else {
+                    JobState::Failed
+                };
+                let description = statuses
+                    .iter()
+                    .map(|status| format!("{:?}", status))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                (state, description)
+            };
+            send(RunnerEvent::RunFinished {
+                success: state == JobState::Passed,
+                description,
+            });
+            state
+        });
+
+        self.active.insert(id, ActiveRun { cancelled, handle });
+    }
+
+    fn run_command(
+        send: &impl Fn(RunnerEvent),
+        command: &mut Command,
+        cancelled: &AtomicBool,
+        mut watchdog: Watchdog,
+    ) -> io::Result<ExitStatus> {
+        // A process group of its own lets a cancel reach the test binaries
+        // that cargo starts, not just cargo itself.
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(command, 0);
+
+        let mut cmd = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()?;
+
+        // Events pass through here so the watchdog sees every one of them.
+        let (events_tx, events_rx) = channel();
+        let stdout = cmd.stdout.take().unwrap();
+        let stderr = cmd.stderr.take().unwrap();
+
+        let events = events_tx.clone();
+        thread::spawn(move || {
+            let mut parser = LineParser::default();
+            let reader = BufReader::new(stdout);
+            for line in reader.lines().map_while(Result::ok) {
+                if let Some(event) = parser.parse(&line) {
+                    let _ = events.send(event);
+                }
+            }
+        });
+
+        thread::spawn(move || {
+            let mut parser = LineParser::default();
+            let reader = BufReader::new(stderr);
+            for line in reader.lines().map_while(Result::ok) {
+                if let Some(event) = parser.parse(&line) {
+                    let _ = events_tx.send(event);
+                }
+            }
+        });
+
+        let mut terminated_at: Option<Instant> = None;
+        let status = loop {
+            while let Ok(event) = events_rx.try_recv() {
+                watchdog.observe(&event);
+                send(event);
+            }
+            if let Some(status) = cmd.try_wait()? {
+                break status;
+            }
+
+            if terminated_at.is_none() {
+                if let Some(timeout) = watchdog.check() {
+                    for event in timeout {
+                        send(event);
+                    }
+                    terminate(&mut cmd, false);
+                    terminated_at = Some(Instant::now());
+                }
+            }
+            if cancelled.load(Ordering::SeqCst) {
+                match terminated_at {
+                    // Ask politely first so nextest can clean up its tests.
+                    None => {
+                        terminate(&mut cmd, false);
+                        terminated_at = Some(Instant::now());
+                    }
+                    Some(at) if at.elapsed() > KILL_GRACE_PERIOD => terminate(&mut cmd, true),
+                    Some(_) => {}
+                }
+            } else if terminated_at.is_some_and(|at| at.elapsed() > KILL_GRACE_PERIOD) {
+                terminate(&mut cmd, true);
+            }
+            thread::sleep(POLL_INTERVAL);
+        };
+
+        // Deliver every result before announcing the end of the run, without
+        // hanging on a stray process that still holds the pipes open.
+        let deadline = Instant::now() + KILL_GRACE_PERIOD;
+        while let Ok(event) =
+            events_rx.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+        {
+            send(event);
+        }
+        Ok(status)
+    }
 
-        app.test_info.clear();
-        app.selected_index = 0;
-        app.selected_test = 0;
+    /// Builds the cargo invocation for a run. It starts from the workspace
+    /// root and selects the package and target explicitly so only the right
+    /// test binary is built.
+    fn command(&self, root: &Path, spec: &RunSpec, backtrace: Backtrace) -> Command {
+        let (cargo_args, test_args) = spec.args();
+        let mut cmd = Command::new("cargo");
+        cmd.current_dir(root)
+            .env("CARGO_TERM_COLOR", "always")
+            .env("RUST_BACKTRACE", backtrace.to_string());
 
-        let test_info = scan_for_tests(dir)?;
-        for (path, tests) in test_info {
-            app.test_info.push(TestInfo { path, tests });
+        // Compiler diagnostics come as JSON so they can be listed, with the
+        // rendered text kept for the output pane.
+        if self.use_nextest {
+            cmd.args(["nextest", "run"])
+                .args(&cargo_args)
+                .args(&self.build_args)
+                .args(["--cargo-message-format", DIAGNOSTIC_FORMAT]);
+            // Captured output is printed with each result so it can be kept
+            // per test.
+            if self.libtest_json {
+                cmd.env("NEXTEST_EXPERIMENTAL_LIBTEST_JSON", "1")
+                    .args(["--message-format", "libtest-json"]);
+            } else {
+                cmd.args(["--success-output", "immediate"])
+                    .args(["--failure-output", "immediate"]);
+            }
+            cmd.arg("--").args(&test_args);
+        } else {
+            cmd.arg("test")
+                .args(&cargo_args)
+                .args(&self.build_args)
+                .args(["--message-format", DIAGNOSTIC_FORMAT])
+                .arg("--")
+                .args(&test_args);
+            if self.libtest_json {
+                cmd.env("RUSTC_BOOTSTRAP", "1").args([
+                    "-Z",
+                    "unstable-options",
+                    "--format",
+                    "json",
+                    "--report-time",
+                ]);
+            }
+            cmd.arg("--show-output");
         }
+        cmd
+    }
 
-        self.output_tx.send(format!(
-            "Rescan complete. Found {} test files.\n",
-            app.test_info.len()
-        ))?;
+    /// Discovers tests in the background; the result is delivered on the scan
+    /// channel since listing tests may have to build the test binaries first.
+    pub fn scan_for_tests(&self, mode: DiscoveryMode, dir: &Path) -> Result<(), Box<dyn Error>> {
+        self.output_tx.send(RunnerEvent::Output(format!(
+            "Rescanning for tests ({})...",
+            mode
+        )))?;
+
+        let tx = self.output_tx.clone();
+        let scan_tx = self.scan_tx.clone();
+        let dir = dir.to_path_buf();
+        let use_nextest = self.use_nextest;
+        let build_args = self.build_args.clone();
+        thread::spawn(move || {
+            let result = Workspace::load(&dir).and_then(|workspace| {
+                let mut test_info = match mode {
+                    DiscoveryMode::Source => scan_for_tests(&workspace)?,
+                    DiscoveryMode::List => list_tests(&workspace, use_nextest, &build_args, &tx)?,
+                };
+                test_info.sort_by(|a, b| {
+                    (&a.package, a.target.kind, &a.target.name, &a.path).cmp(&(
+                        &b.package,
+                        b.target.kind,
+                        &b.target.name,
+                        &b.path,
+                    ))
+                });
+                Ok((workspace, test_info))
+            });
+            match result {
+                Ok((workspace, test_info)) => {
+                    let _ = tx.send(RunnerEvent::Output(format!(
+                        "Rescan complete. Found {} test files.",
+                        test_info.len()
+                    )));
+                    let _ = scan_tx.send((workspace, test_info));
+                }
+                Err(err) => {
+                    let _ = tx.send(RunnerEvent::Output(format!("Rescan failed: {}", err)));
+                }
+            }
+        });
 
         Ok(())
     }
 }
+
+/// Decides when a test command has run for too long. Tests that report
+/// their start (libtest's JSON format) are timed individually; otherwise the
+/// tests still running in a suite are known to have run at least since the
+/// last result, which is what gets timed.
+struct Watchdog {
+    test_timeout: Option<Duration>,
+    deadline: Option<Instant>,
+    /// Test binary cargo is running, as named in its `Running` line.
+    binary: Option<String>,
+    /// Time of the last progress in the suite that is running, if any.
+    suite_progress: Option<Instant>,
+    started: HashMap<String, Instant>,
+}
+
+impl Watchdog {
+    fn new(test_timeout: Option<Duration>, deadline: Option<Instant>) -> Self {
+        Watchdog {
+            test_timeout,
+            deadline,
+            binary: None,
+            suite_progress: None,
+            started: HashMap::new(),
+        }
+    }
+
+    fn observe(&mut self, event: &RunnerEvent) {
+        match event {
+            RunnerEvent::Output(line) => {
+                if let Some(binary) = running_binary(line) {
+                    self.binary = Some(binary);
+                } else if line.starts_with("test result:") {
+                    self.suite_progress = None;
+                    self.started.clear();
+                }
+            }
+            RunnerEvent::SuiteStarted { .. } => self.suite_progress = Some(Instant::now()),
+            RunnerEvent::TestStarted { name } => {
+                self.started.insert(name.clone(), Instant::now());
+            }
+            RunnerEvent::TestFinished(result) => {
+                self.started.remove(&result.name);
+                if self.suite_progress.is_some() {
+                    self.suite_progress = Some(Instant::now());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Events reporting the tests that timed out, once the command is due to
+    /// be killed.
+    fn check(&self) -> Option<Vec<RunnerEvent>> {
+        let now = Instant::now();
+        let run_expired = self.deadline.is_some_and(|deadline| now >= deadline);
+        let test_expired = |since: Instant| {
+            run_expired
+                || self
+                    .test_timeout
+                    .is_some_and(|timeout| now - since >= timeout)
+        };
+
+        if !self.started.is_empty() {
+            if !self.started.values().any(|since| test_expired(*since)) {
+                return None;
+            }
+            let events = self
+                .started
+                .iter()
+                .filter(|(_, since)| test_expired(**since))
+                .map(|(name, since)| {
+                    let ran_for = now - *since;
+                    RunnerEvent::TestFinished(TestResult {
+                        binary: self.binary.clone(),
+                        name: name.clone(),
+                        status: TestStatus::TimedOut,
+                        stdout: None,
+                        duration: Some(ran_for),
+                        line: None,
+                    })
+                })
+                .collect();
+            return Some(events);
+        }
+
+        match self.suite_progress {
+            Some(since) if test_expired(since) => Some(vec![RunnerEvent::SuiteTimedOut {
+                binary: self.binary.clone(),
+                ran_for: now - since,
+            }]),
+            None if run_expired => Some(Vec::new()),
+            _ => None,
+        }
+    }
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    format!("{:.1}s", duration.as_secs_f64())
+}
+
+/// A command as it would be typed in a shell, with the variables it sets.
+fn command_line(command: &Command) -> String {
+    let mut words: Vec<String> = command
+        .get_envs()
+        .filter_map(|(key, value)| {
+            Some(format!(
+                "{}={}",
+                key.to_string_lossy(),
+                value?.to_string_lossy()
+            ))
+        })
+        .collect();
+    words.push(command.get_program().to_string_lossy().to_string());
+    words.extend(
+        command
+            .get_args()
+            .map(|arg| arg.to_string_lossy().to_string()),
+    );
+    words.join(" ")
+}
+
+/// Signals the process group of a test command: SIGTERM, or SIGKILL when
+/// `force` is set.
+#[cfg(unix)]
+fn terminate(child: &mut Child, force: bool) {
+    let signal = if force { libc::SIGKILL } else { libc::SIGTERM };
+    // SAFETY: kill(2) has no memory safety requirements; the negative pid
+    // addresses the group the child leads.
+    unsafe {
+        libc::kill(-(child.id() as libc::pid_t), signal);
+    }
+}
+
+#[cfg(not(unix))]
+fn terminate(child: &mut Child, _force: bool) {
+    let _ = child.kill();
+}
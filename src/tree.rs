@@ -0,0 +1,185 @@
+use crate::app::TestInfo;
+use crate::test_runner::RunSpec;
+use crate::workspace::{Target, TargetKind};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum NodeKind {
+    Workspace,
+    Package,
+    Target,
+    Module,
+    Test,
+}
+
+/// Identifies a test by its position in `App::test_info`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TestRef {
+    pub file: usize,
+    pub test: usize,
+}
+
+/// A node of the workspace → crate → target → module → test hierarchy.
+pub struct TreeNode {
+    pub kind: NodeKind,
+    pub label: String,
+    /// Stable key used to remember which nodes are expanded across rescans.
+    pub id: String,
+    pub package: Option<String>,
+    pub target: Option<Target>,
+    /// Module path of a module node, or the full name of a test node.
+    pub path: Vec<String>,
+    pub children: Vec<TreeNode>,
+    /// Every test below this node.
+    pub tests: Vec<TestRef>,
+}
+
+pub struct TreeRow<'a> {
+    pub depth: usize,
+    pub node: &'a TreeNode,
+}
+
+impl TreeNode {
+    fn new(kind: NodeKind, label: String, parent: Option<&TreeNode>) -> Self {
+        let id = match parent {
+            Some(parent) => format!("{}/{}", parent.id, label),
+            None => label.clone(),
+        };
+        let mut path: Vec<String> = parent.map(|parent| parent.path.clone()).unwrap_or_default();
+        if matches!(kind, NodeKind::Module | NodeKind::Test) {
+            path.push(label.clone());
+        }
+        TreeNode {
+            kind,
+            label,
+            id,
+            package: parent.and_then(|parent| parent.package.clone()),
+            target: parent.and_then(|parent| parent.target.clone()),
+            path,
+            children: Vec::new(),
+            tests: Vec::new(),
+        }
+    }
+
+    fn child(&mut self, kind: NodeKind, label: &str) -> &mut TreeNode {
+        let index = match self
+            .children
+            .iter()
+            .position(|child| child.kind == kind && child.label == label)
+        {
+            Some(index) => index,
+            None => {
+                let child = TreeNode::new(kind, label.to_string(), Some(self));
+                self.children.push(child);
+                self.children.len() - 1
+            }
+        };
+        &mut self.children[index]
+    }
+
+    pub fn is_leaf(&self) -> bool {
+        self.children.is_empty()
+    }
+
+    /// What running this node means: every test below it. Modules name
+    /// their tests exactly, as a `module::` filter also matches modules of
+    /// the same name elsewhere in the crate.
+    pub fn run_spec(&self) -> RunSpec {
+        let (filters, exact) = match self.kind {
+            NodeKind::Module | NodeKind::Test => (self.test_names(), true),
+            _ => (Vec::new(), false),
+        };
+        RunSpec {
+            label: self.label.clone(),
+            package: self.package.clone(),
+            target: self.target.clone(),
+            filters,
+            exact,
+        }
+    }
+
+    /// Full names of the tests below this node.
+    fn test_names(&self) -> Vec<String> {
+        if self.kind == NodeKind::Test {
+            return vec![self.path.join("::")];
+        }
+        self.children
+            .iter()
+            .flat_map(TreeNode::test_names)
+            .collect()
+    }
+
+    /// Ids of this node and every node below it.
+    pub fn ids(&self) -> Vec<String> {
+        let mut ids = vec![self.id.clone()];
+        for child in &self.children {
+            ids.extend(child.ids());
+        }
+        ids
+    }
+}
+
+pub fn build_tree(name: &str, test_info: &[TestInfo]) -> TreeNode {
+    let mut root = TreeNode::new(NodeKind::Workspace, name.to_string(), None);
+
+    for (file, info) in test_info.iter().enumerate() {
+        for (index, test) in info.tests.iter().enumerate() {
+            let test_ref = TestRef { file, test: index };
+            root.tests.push(test_ref);
+
+            let package = root.child(NodeKind::Package, &info.package);
+            package.package = Some(info.package.clone());
+            package.tests.push(test_ref);
+
+            let label = match info.target.kind {
+                TargetKind::Lib => info.target.kind.to_string(),
+                kind => format!("{} {}", kind, info.target.name),
+            };
+            let mut node = package.child(NodeKind::Target, &label);
+            node.target = Some(info.target.clone());
+            node.tests.push(test_ref);
+
+            let mut segments: Vec<&str> = test.name.split("::").collect();
+            let name = segments.pop().unwrap_or_default();
+            for segment in segments {
+                node = node.child(NodeKind::Module, segment);
+                node.tests.push(test_ref);
+            }
+            node.child(NodeKind::Test, name).tests.push(test_ref);
+        }
+    }
+
+    root
+}
+
+/// Flattens the tree into the rows that are currently shown. With a filter,
+/// only nodes with matching tests are shown, all of them expanded.
+pub fn visible_rows<'a, T>(
+    root: &'a TreeNode,
+    expanded: &HashSet<String>,
+    filter: Option<&HashMap<TestRef, T>>,
+) -> Vec<TreeRow<'a>> {
+    let mut rows = Vec::new();
+    push_rows(root, 0, expanded, filter, &mut rows);
+    rows
+}
+
+fn push_rows<'a, T>(
+    node: &'a TreeNode,
+    depth: usize,
+    expanded: &HashSet<String>,
+    filter: Option<&HashMap<TestRef, T>>,
+    rows: &mut Vec<TreeRow<'a>>,
+) {
+    if let Some(filter) = filter {
+        if depth > 0 && !node.tests.iter().any(|test| filter.contains_key(test)) {
+            return;
+        }
+    }
+    rows.push(TreeRow { depth, node });
+    if filter.is_some() || expanded.contains(&node.id) {
+        for child in &node.children {
+            push_rows(child, depth + 1, expanded, filter, rows);
+        }
+    }
+}
@@ -1,16 +1,20 @@
-use crate::app::App;
+use crate::app::{App, OutputSource, SearchMatch, TestStatus};
+use crate::config::Theme;
+use crate::test_runner::{format_duration, JobState};
+use crate::tree::NodeKind;
 use ansi_parser::{AnsiParser, AnsiSequence, Output};
 use crossterm::{
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use std::io;
+use std::time::Duration;
 use tui::{
     backend::CrosstermBackend,
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
     text::{Span, Spans},
-    widgets::{Block, Borders, List, ListItem, Paragraph, Wrap},
+    widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap},
     Frame, Terminal,
 };
 
@@ -28,6 +32,15 @@ pub fn restore_terminal(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -
     terminal.show_cursor()
 }
 
+/// Takes the terminal back after `restore_terminal`, e.g. once an editor
+/// has exited.
+pub fn resume_terminal(terminal: &mut Terminal<CrosstermBackend<io::Stdout>>) -> io::Result<()> {
+    enable_raw_mode()?;
+    execute!(terminal.backend_mut(), EnterAlternateScreen)?;
+    terminal.hide_cursor()?;
+    terminal.clear()
+}
+
 pub fn draw(f: &mut Frame<CrosstermBackend<io::Stdout>>, app: &mut App) {
     let chunks = Layout::default()
         .direction(Direction::Horizontal)
@@ -36,34 +49,69 @@ pub fn draw(f: &mut Frame<CrosstermBackend<io::Stdout>>, app: &mut App) {
 
     let left_chunks = Layout::default()
         .direction(Direction::Vertical)
-        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)].as_ref())
+        .constraints(
+            [
+                Constraint::Percentage(45),
+                Constraint::Percentage(35),
+                Constraint::Percentage(20),
+            ]
+            .as_ref(),
+        )
         .split(chunks[0]);
 
-    draw_test_files(f, app, left_chunks[0]);
-    draw_tests(f, app, left_chunks[1]);
+    if app.show_history {
+        draw_history(f, app, left_chunks[0]);
+    } else {
+        draw_test_files(f, app, left_chunks[0]);
+    }
+    if app.show_diagnostics {
+        draw_diagnostics(f, app, left_chunks[1]);
+    } else {
+        draw_tests(f, app, left_chunks[1]);
+    }
+    draw_jobs(f, app, left_chunks[2]);
     draw_test_output(f, app, chunks[1]);
 }
 
 fn draw_test_files(f: &mut Frame<CrosstermBackend<io::Stdout>>, app: &App, area: Rect) {
-    let items: Vec<ListItem> = app
-        .test_info
+    let rows = app.visible_rows();
+    let items: Vec<ListItem> = rows
         .iter()
         .enumerate()
-        .map(|(i, info)| {
+        .map(|(i, row)| {
+            let node = row.node;
             let style = if i == app.selected_index && app.active_pane == 0 {
-                Style::default().fg(Color::Black).bg(Color::LightBlue)
+                Style::default().fg(Color::Black).bg(app.theme.selection)
             } else {
                 Style::default().fg(Color::White)
             };
-            ListItem::new(Spans::from(vec![Span::styled(
-                info.path.to_string_lossy(),
-                style,
-            )]))
+            let marker = if node.is_leaf() {
+                "  "
+            } else if app.filter_matches.is_some() || app.expanded.contains(&node.id) {
+                "▾ "
+            } else {
+                "▸ "
+            };
+            let tests = app.node_tests(node);
+            let status = TestStatus::aggregate(tests.iter().map(|t| app.test(*t).status));
+            let mut spans = vec![
+                Span::raw("  ".repeat(row.depth)),
+                Span::raw(marker),
+                status_span(&app.theme, status),
+            ];
+            spans.extend(highlighted(&node.label, &app.label_matches(node), style));
+            if node.kind != NodeKind::Test {
+                spans.push(Span::styled(
+                    format!(" ({})", tests.len()),
+                    Style::default().fg(Color::DarkGray),
+                ));
+            }
+            ListItem::new(Spans::from(spans))
         })
         .collect();
 
     let border_style = if app.active_pane == 0 {
-        Style::default().fg(Color::Rgb(255, 165, 0))
+        Style::default().fg(app.theme.active_border)
     } else {
         Style::default().fg(Color::White)
     };
@@ -71,51 +119,448 @@ fn draw_test_files(f: &mut Frame<CrosstermBackend<io::Stdout>>, app: &App, area:
     let list = List::new(items)
         .block(
             Block::default()
-                .title("Test Files")
+                .title(tree_title(app))
                 .borders(Borders::ALL)
                 .border_style(border_style),
         )
         .highlight_style(Style::default().add_modifier(Modifier::BOLD))
         .highlight_symbol("> ");
 
-    f.render_widget(list, area);
+    let mut state = ListState::default();
+    state.select(Some(app.selected_index));
+    f.render_stateful_widget(list, area, &mut state);
+}
+
+fn draw_history(f: &mut Frame<CrosstermBackend<io::Stdout>>, app: &App, area: Rect) {
+    let runs = app
+        .history
+        .as_ref()
+        .map_or(&[][..], |history| history.runs.as_slice());
+    let items: Vec<ListItem> = if runs.is_empty() {
+        vec![ListItem::new("No runs recorded yet")]
+    } else {
+        runs.iter()
+            .rev()
+            .enumerate()
+            .map(|(i, run)| {
+                let style = if i == app.selected_run && app.active_pane == 0 {
+                    Style::default().fg(Color::Black).bg(app.theme.selection)
+                } else {
+                    Style::default().fg(Color::White)
+                };
+                let status = if run.success {
+                    TestStatus::Passed
+                } else {
+                    TestStatus::Failed
+                };
+                let marker = if app.compare_base.as_ref() == Some(&run.id) {
+                    "◆ "
+                } else {
+                    "  "
+                };
+                let (passed, failed, _) = run.counts();
+                let mut details = format!(" {}✓ {}✗", passed, failed);
+                if let Some(revision) = &run.revision {
+                    details.push_str(&format!(" {}", revision.short()));
+                }
+                ListItem::new(Spans::from(vec![
+                    Span::styled(marker, Style::default().fg(Color::Yellow)),
+                    status_span(&app.theme, status),
+                    Span::styled(
+                        format!("{} ", run.started.format("%m-%d %H:%M:%S")),
+                        Style::default().fg(Color::DarkGray),
+                    ),
+                    Span::styled(run.label.clone(), style),
+                    Span::styled(details, Style::default().fg(Color::DarkGray)),
+                ]))
+            })
+            .collect()
+    };
+
+    let border_style = if app.active_pane == 0 {
+        Style::default().fg(app.theme.active_border)
+    } else {
+        Style::default().fg(Color::White)
+    };
+    let title = if app.compare_base.is_some() {
+        format!("History ({} runs, comparing with ◆)", runs.len())
+    } else {
+        format!("History ({} runs, c: compare)", runs.len())
+    };
+
+    let list = List::new(items).block(
+        Block::default()
+            .title(title)
+            .borders(Borders::ALL)
+            .border_style(border_style),
+    );
+    let mut state = ListState::default();
+    state.select(Some(app.selected_run));
+    f.render_stateful_widget(list, area, &mut state);
 }
 
 fn draw_tests(f: &mut Frame<CrosstermBackend<io::Stdout>>, app: &App, area: Rect) {
-    let items: Vec<ListItem> = if let Some(info) = app.test_info.get(app.selected_index) {
-        info.tests
+    let tests = app.selected_tests();
+    let items: Vec<ListItem> = if tests.is_empty() {
+        vec![ListItem::new("No tests found")]
+    } else {
+        tests
             .iter()
             .enumerate()
-            .map(|(i, test)| {
+            .map(|(i, test_ref)| {
+                let test = app.test(*test_ref);
                 let style = if i == app.selected_test && app.active_pane == 1 {
-                    Style::default().fg(Color::Black).bg(Color::LightBlue)
+                    Style::default().fg(Color::Black).bg(app.theme.selection)
+                } else if test.is_ignored() {
+                    Style::default().fg(Color::DarkGray)
                 } else {
                     Style::default().fg(Color::White)
                 };
-                ListItem::new(Spans::from(vec![Span::styled(test, style)]))
+                let mut spans = vec![status_span(&app.theme, test.status)];
+                spans.extend(highlighted(&test.name, app.test_matches(*test_ref), style));
+                if test.is_ignored() {
+                    spans.push(Span::styled(
+                        " (ignored)",
+                        Style::default().fg(Color::DarkGray),
+                    ));
+                }
+                if let (TestStatus::TimedOut, Some(duration)) = (test.status, test.duration) {
+                    spans.push(Span::styled(
+                        format!(" (timed out after {})", format_duration(duration)),
+                        Style::default().fg(Color::Magenta),
+                    ));
+                }
+                if let (Some(duration), true) = (
+                    test.duration,
+                    matches!(test.status, TestStatus::Passed | TestStatus::Failed),
+                ) {
+                    if test.is_regressed() {
+                        spans.push(Span::styled(
+                            format!(
+                                " {} ▲ x{:.1}",
+                                format_test_duration(duration),
+                                test.slowdown().unwrap_or_default()
+                            ),
+                            Style::default().fg(Color::Red),
+                        ));
+                    } else {
+                        spans.push(Span::styled(
+                            format!(" {}", format_test_duration(duration)),
+                            Style::default().fg(Color::DarkGray),
+                        ));
+                    }
+                }
+                if let Some(score) = test.flakiness {
+                    spans.push(Span::styled(
+                        format!(" flaky {:.0}%", score * 100.0),
+                        Style::default().fg(Color::Yellow),
+                    ));
+                }
+                if let Some((passed, total)) = test.stress {
+                    let color = if passed == total {
+                        app.theme.passed
+                    } else {
+                        app.theme.failed
+                    };
+                    spans.push(Span::styled(
+                        format!(" stress {}/{}", passed, total),
+                        Style::default().fg(color),
+                    ));
+                }
+                ListItem::new(Spans::from(spans))
             })
             .collect()
-    } else {
-        vec![ListItem::new("No tests found")]
     };
 
     let border_style = if app.active_pane == 1 {
-        Style::default().fg(Color::Rgb(255, 165, 0))
+        Style::default().fg(app.theme.active_border)
     } else {
         Style::default().fg(Color::White)
     };
 
+    // Name the file of the selected test, since a node can span many files.
+    let title = match tests.get(app.selected_test) {
+        _ if app.show_slowest => format!("Slowest Tests (by {}, o: sort)", app.slowest_order),
+        Some(test_ref) => format!(
+            "Tests ({})",
+            app.relative_path(&app.test_info[test_ref.file].path)
+                .display()
+        ),
+        None => "Tests".to_string(),
+    };
+
     let list = List::new(items)
         .block(
             Block::default()
-                .title("Tests")
+                .title(title)
                 .borders(Borders::ALL)
                 .border_style(border_style),
         )
         .highlight_style(Style::default().add_modifier(Modifier::BOLD))
         .highlight_symbol("> ");
 
-    f.render_widget(list, area);
+    let mut state = ListState::default();
+    state.select(Some(app.selected_test));
+    f.render_stateful_widget(list, area, &mut state);
+}
+
+fn draw_diagnostics(f: &mut Frame<CrosstermBackend<io::Stdout>>, app: &App, area: Rect) {
+    let items: Vec<ListItem> = if app.diagnostics.is_empty() {
+        vec![ListItem::new("No diagnostics")]
+    } else {
+        app.diagnostics
+            .iter()
+            .enumerate()
+            .map(|(i, diagnostic)| {
+                let color = if diagnostic.is_error() {
+                    Color::Red
+                } else {
+                    Color::Yellow
+                };
+                let style = if i == app.selected_diagnostic && app.active_pane == 1 {
+                    Style::default().fg(Color::Black).bg(app.theme.selection)
+                } else {
+                    Style::default().fg(Color::White)
+                };
+                let location = match &diagnostic.path {
+                    Some(path) => {
+                        format!(
+                            "{}:{}:{} ",
+                            path.display(),
+                            diagnostic.line,
+                            diagnostic.column
+                        )
+                    }
+                    None => String::new(),
+                };
+                ListItem::new(Spans::from(vec![
+                    Span::styled(
+                        format!("{}: ", diagnostic.level),
+                        Style::default().fg(color),
+                    ),
+                    Span::styled(location, Style::default().fg(Color::DarkGray)),
+                    Span::styled(diagnostic.message.clone(), style),
+                ]))
+            })
+            .collect()
+    };
+
+    let border_style = if app.active_pane == 1 {
+        Style::default().fg(app.theme.active_border)
+    } else {
+        Style::default().fg(Color::White)
+    };
+    let errors = app
+        .diagnostics
+        .iter()
+        .filter(|diagnostic| diagnostic.is_error())
+        .count();
+    let title = format!(
+        "Diagnostics ({} errors, {} warnings)",
+        errors,
+        app.diagnostics.len() - errors
+    );
+
+    let list = List::new(items).block(
+        Block::default()
+            .title(title)
+            .borders(Borders::ALL)
+            .border_style(border_style),
+    );
+    let mut state = ListState::default();
+    state.select(Some(app.selected_diagnostic));
+    f.render_stateful_widget(list, area, &mut state);
+}
+
+fn draw_jobs(f: &mut Frame<CrosstermBackend<io::Stdout>>, app: &App, area: Rect) {
+    let items: Vec<ListItem> = if app.jobs.is_empty() {
+        vec![ListItem::new("No jobs")]
+    } else {
+        app.jobs
+            .iter()
+            .enumerate()
+            .map(|(i, job)| {
+                let (state, color) = match job.state {
+                    JobState::Queued => ("queued   ", Color::DarkGray),
+                    JobState::Running => ("running  ", app.theme.running),
+                    JobState::Passed => ("passed   ", app.theme.passed),
+                    JobState::Failed => ("failed   ", app.theme.failed),
+                    JobState::Cancelled => ("cancelled", Color::DarkGray),
+                    JobState::TimedOut => ("timed out", Color::Magenta),
+                };
+                let style = if i == app.selected_job && app.active_pane == 2 {
+                    Style::default().fg(Color::Black).bg(app.theme.selection)
+                } else {
+                    Style::default().fg(Color::White)
+                };
+                let mut spans = vec![
+                    Span::styled(
+                        format!("#{} ", job.id),
+                        Style::default().fg(Color::DarkGray),
+                    ),
+                    Span::styled(state, Style::default().fg(color)),
+                    Span::raw(" "),
+                    Span::styled(job.label.clone(), style),
+                ];
+                if let Some(elapsed) = job.elapsed() {
+                    spans.push(Span::styled(
+                        format!(" {}", format_duration(elapsed)),
+                        Style::default().fg(Color::DarkGray),
+                    ));
+                }
+                ListItem::new(Spans::from(spans))
+            })
+            .collect()
+    };
+
+    let border_style = if app.active_pane == 2 {
+        Style::default().fg(app.theme.active_border)
+    } else {
+        Style::default().fg(Color::White)
+    };
+    let queued = app
+        .jobs
+        .iter()
+        .filter(|job| job.state == JobState::Queued)
+        .count();
+    let title = if queued > 0 {
+        format!("Jobs ({} queued, RUST_BACKTRACE={})", queued, app.backtrace)
+    } else {
+        format!("Jobs (RUST_BACKTRACE={})", app.backtrace)
+    };
+
+    let list = List::new(items).block(
+        Block::default()
+            .title(title)
+            .borders(Borders::ALL)
+            .border_style(border_style),
+    );
+    let mut state = ListState::default();
+    state.select(Some(app.selected_job));
+    f.render_stateful_widget(list, area, &mut state);
+}
+
+/// Marks the search matches on a line of output on top of its ANSI styles;
+/// the current match stands out from the others.
+fn highlight_matches<'a>(app: &App, line: usize, spans: Vec<Span<'a>>) -> Spans<'a> {
+    let matches: Vec<(usize, &SearchMatch)> = app
+        .search_matches
+        .iter()
+        .enumerate()
+        .filter(|(_, m)| m.line == line)
+        .collect();
+    if matches.is_empty() {
+        return Spans::from(spans);
+    }
+    let style_at = |offset: usize, style: Style| match matches
+        .iter()
+        .find(|(_, m)| (m.start..m.start + m.len).contains(&offset))
+    {
+        Some((index, _)) if *index == app.current_match => {
+            style.fg(Color::Black).bg(Color::LightRed)
+        }
+        Some(_) => style.fg(Color::Black).bg(Color::Yellow),
+        None => style,
+    };
+
+    let mut result = Vec::new();
+    let mut offset = 0;
+    for span in spans {
+        let mut current = String::new();
+        let mut current_style = None;
+        for c in span.content.chars() {
+            let style = style_at(offset, span.style);
+            if current_style.is_some_and(|current| current != style) {
+                result.push(Span::styled(
+                    std::mem::take(&mut current),
+                    current_style.unwrap(),
+                ));
+            }
+            current_style = Some(style);
+            current.push(c);
+            offset += 1;
+        }
+        if let Some(style) = current_style {
+            result.push(Span::styled(current, style));
+        }
+    }
+    Spans::from(result)
+}
+
+fn output_title(app: &App) -> String {
+    match app.output_source {
+        OutputSource::Log => "Log".to_string(),
+        OutputSource::Job(id) => match app.jobs.iter().find(|job| job.id == id) {
+            Some(job) => format!("Job #{} ({})", id, job.label),
+            None => format!("Job #{}", id),
+        },
+        OutputSource::Test(test_ref) => format!("Output of {}", app.test(test_ref).name),
+        OutputSource::Failure(test_ref) => format!("Failure of {}", app.test(test_ref).name),
+        OutputSource::History => match app.selected_run() {
+            Some(run) if app.compare_base.as_ref().is_some_and(|id| *id != run.id) => {
+                format!("Changes in run {}", run.title())
+            }
+            Some(run) => format!("Run {}", run.title()),
+            None => "History".to_string(),
+        },
+    }
+}
+
+fn tree_title(app: &App) -> String {
+    let mut title = format!("Test Files ({})", app.discovery_mode);
+    if app.editing_filter || !app.filter.is_empty() {
+        title.push_str(&format!(" /{}", app.filter));
+        if app.editing_filter {
+            title.push('_');
+        }
+    }
+    title
+}
+
+/// Splits `text` into spans with the characters at `indices` emphasized.
+fn highlighted<'a>(text: &str, indices: &[usize], style: Style) -> Vec<Span<'a>> {
+    if indices.is_empty() {
+        return vec![Span::styled(text.to_string(), style)];
+    }
+    let matched = style.fg(Color::Yellow).add_modifier(Modifier::BOLD);
+    let mut spans: Vec<Span> = Vec::new();
+    let mut current = String::new();
+    let mut current_matched = false;
+    for (index, c) in text.chars().enumerate() {
+        let is_match = indices.contains(&index);
+        if is_match != current_matched && !current.is_empty() {
+            let style = if current_matched { matched } else { style };
+            spans.push(Span::styled(std::mem::take(&mut current), style));
+        }
+        current_matched = is_match;
+        current.push(c);
+    }
+    if !current.is_empty() {
+        let style = if current_matched { matched } else { style };
+        spans.push(Span::styled(current, style));
+    }
+    spans
+}
+
+/// Test durations are usually far below a second, so they get milliseconds.
+fn format_test_duration(duration: Duration) -> String {
+    if duration < Duration::from_secs(1) {
+        format!("{}ms", duration.as_millis())
+    } else {
+        format_duration(duration)
+    }
+}
+
+fn status_span(theme: &Theme, status: TestStatus) -> Span<'static> {
+    let (icon, color) = match status {
+        TestStatus::Pending => ("· ", Color::DarkGray),
+        TestStatus::Running => ("◌ ", theme.running),
+        TestStatus::Passed => ("✓ ", theme.passed),
+        TestStatus::Failed => ("✗ ", theme.failed),
+        TestStatus::Ignored => ("- ", Color::DarkGray),
+        TestStatus::TimedOut => ("⏱ ", Color::Magenta),
+    };
+    Span::styled(icon, Style::default().fg(color))
 }
 
 fn draw_test_output(f: &mut Frame<CrosstermBackend<io::Stdout>>, app: &App, area: Rect) {
@@ -125,8 +570,19 @@ fn draw_test_output(f: &mut Frame<CrosstermBackend<io::Stdout>>, app: &App, area
     let start_line = app.output_scroll;
     let end_line = (start_line + visible_height).min(total_lines);
 
-    let output_lines: Vec<Spans> = app
-        .test_output
+    let text = app.output_text();
+    if text.is_empty() {
+        if let OutputSource::Test(test_ref) = app.output_source {
+            let block = Block::default()
+                .title(format!("Output of {}", app.test(test_ref).name))
+                .borders(Borders::ALL);
+            let message = Paragraph::new("No output captured for this test").block(block);
+            f.render_widget(message, area);
+            return;
+        }
+    }
+
+    let output_lines: Vec<Spans> = text
         .lines()
         .skip(start_line)
         .take(end_line - start_line)
@@ -145,6 +601,14 @@ fn draw_test_output(f: &mut Frame<CrosstermBackend<io::Stdout>>, app: &App, area
                                 match mode {
                                     0 => current_style = Style::default(),
                                     1 => current_style = current_style.add_modifier(Modifier::BOLD),
+                                    4 => {
+                                        current_style =
+                                            current_style.add_modifier(Modifier::UNDERLINED)
+                                    }
+                                    7 => {
+                                        current_style =
+                                            current_style.add_modifier(Modifier::REVERSED)
+                                    }
                                     30..=37 => {
                                         let color = match mode - 30 {
                                             0 => Color::Black,
@@ -173,6 +637,7 @@ fn draw_test_output(f: &mut Frame<CrosstermBackend<io::Stdout>>, app: &App, area
                                         };
                                         current_style = current_style.bg(color);
                                     }
+                                    90 => current_style = current_style.fg(Color::DarkGray),
                                     _ => {}
                                 }
                             }
@@ -180,21 +645,52 @@ fn draw_test_output(f: &mut Frame<CrosstermBackend<io::Stdout>>, app: &App, area
                     }
                 }
             }
-            Spans::from(spans)
+            spans
         })
+        .enumerate()
+        .map(|(i, spans)| highlight_matches(app, start_line + i, spans))
         .collect();
 
     let scroll_indicator = if total_lines > visible_height {
         format!("{}/{}", start_line + 1, total_lines)
     } else {
-        format!("All")
+        "All".to_string()
+    };
+
+    let progress = match app.run_progress() {
+        Some((done, total, failed)) => format!(" {}/{} done, {} failed", done, total, failed),
+        None => String::new(),
+    };
+
+    let search = if app.editing_search {
+        format!(" | /{}_", app.search)
+    } else if !app.search.is_empty() {
+        let current = if app.search_matches.is_empty() {
+            0
+        } else {
+            app.current_match + 1
+        };
+        format!(
+            " | /{} {}/{}",
+            app.search,
+            current,
+            app.search_matches.len()
+        )
+    } else {
+        String::new()
     };
 
     let block = Block::default()
-        .title(format!("Test Output (Scroll: {})", scroll_indicator))
+        .title(format!(
+            "{} (Scroll: {}{}){}",
+            output_title(app),
+            scroll_indicator,
+            search,
+            progress
+        ))
         .borders(Borders::ALL)
-        .border_style(Style::default().fg(if app.active_pane == 2 {
-            Color::Rgb(255, 165, 0)
+        .border_style(Style::default().fg(if app.active_pane == 3 {
+            app.theme.active_border
         } else {
             Color::White
         }));
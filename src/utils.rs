@@ -1,33 +1,358 @@
-use ignore::WalkBuilder;
-use regex::Regex;
+use crate::app::{TestCase, TestInfo, TestStatus};
+use crate::workspace::Workspace;
+use log::debug;
+use std::collections::HashSet;
 use std::error::Error;
 use std::fs;
 use std::path::{Path, PathBuf};
+use syn::{Attribute, Expr, Item, Lit, Meta};
 
-pub fn scan_for_tests<P: AsRef<Path>>(
-    dir: P,
-) -> Result<Vec<(PathBuf, Vec<String>)>, Box<dyn Error>> {
-    let test_regex =
-        Regex::new(r"(?m)#\[(cfg\(test\)|test|(tokio::)?test)\][\s\n]*(async\s+)?fn\s+(\w+)")?;
-    let mut test_info = Vec::new();
+/// A `mod foo;` declaration resolved to the file that holds its contents.
+struct ModuleFile {
+    path: PathBuf,
+    module_path: Vec<String>,
+    mod_rs: bool,
+}
 
-    let walker = WalkBuilder::new(dir).hidden(false).git_ignore(true).build();
+/// A source file reached from a crate root, with the tests it defines.
+pub struct ScannedFile {
+    pub path: PathBuf,
+    pub module_path: Vec<String>,
+    pub tests: Vec<TestCase>,
+}
 
-    for entry in walker {
-        let entry = entry?;
-        let path = entry.path();
-        if path.is_file() && path.extension().map_or(false, |ext| ext == "rs") {
-            let content = fs::read_to_string(path)?;
-            let tests: Vec<String> = test_regex
-                .captures_iter(&content)
-                .filter_map(|cap| cap.get(4).map(|m| m.as_str().to_string()))
-                .collect();
+/// Scans every test target of the workspace members, starting from the crate
+/// roots reported by `cargo metadata`.
+pub fn scan_for_tests(workspace: &Workspace) -> Result<Vec<TestInfo>, Box<dyn Error>> {
+    let mut test_info = Vec::new();
 
-            if !tests.is_empty() {
-                test_info.push((path.to_path_buf(), tests));
+    for package in &workspace.packages {
+        for target in &package.targets {
+            for file in scan_crate(&target.src_path)? {
+                if !file.tests.is_empty() {
+                    test_info.push(TestInfo {
+                        path: file.path,
+                        package: package.name.clone(),
+                        target: target.clone(),
+                        tests: file.tests,
+                    });
+                }
             }
         }
     }
 
     Ok(test_info)
 }
+
+/// Follows `mod` declarations from a crate root so that each file is scanned
+/// with the module path it actually has in the compiled crate.
+pub fn scan_crate(root: &Path) -> Result<Vec<ScannedFile>, Box<dyn Error>> {
+    let mut scanned = Vec::new();
+    let mut pending = vec![ModuleFile {
+        path: root.to_path_buf(),
+        module_path: Vec::new(),
+        mod_rs: true,
+    }];
+    let mut visited = HashSet::new();
+
+    while let Some(module) = pending.pop() {
+        if !visited.insert(module.path.clone()) {
+            continue;
+        }
+        let Some(file) = parse_file(&module.path)? else {
+            continue;
+        };
+
+        let file_dir = module.path.parent().unwrap_or(Path::new("")).to_path_buf();
+        let dir = if module.mod_rs {
+            file_dir.clone()
+        } else {
+            file_dir.join(module.module_path.last().map_or("", String::as_str))
+        };
+
+        let mut tests = Vec::new();
+        let scope = Scope {
+            module_path: module.module_path.clone(),
+            dir,
+            path_base: file_dir,
+        };
+        collect_tests(&file.items, &scope, &mut tests, &mut pending);
+
+        scanned.push(ScannedFile {
+            path: module.path,
+            module_path: module.module_path,
+            tests,
+        });
+    }
+
+    Ok(scanned)
+}
+
+fn parse_file(path: &Path) -> Result<Option<syn::File>, Box<dyn Error>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    match syn::parse_file(&content) {
+        Ok(file) => Ok(Some(file)),
+        Err(err) => {
+            debug!("Skipping {}: {}", path.display(), err);
+            Ok(None)
+        }
+    }
+}
+
+/// Where we are while walking the items of a file.
+struct Scope {
+    module_path: Vec<String>,
+    /// Directory that holds the files of `mod foo;` declarations.
+    dir: PathBuf,
+    /// Directory that `#[path = "..."]` attributes are relative to.
+    path_base: PathBuf,
+}
+
+fn collect_tests(
+    items: &[Item],
+    scope: &Scope,
+    tests: &mut Vec<TestCase>,
+    submodules: &mut Vec<ModuleFile>,
+) {
+    for item in items {
+        match item {
+            Item::Fn(func) if func.attrs.iter().any(is_test_attribute) => {
+                let mut name = scope.module_path.clone();
+                name.push(func.sig.ident.to_string());
+                tests.push(TestCase {
+                    name: name.join("::"),
+                    attributes: func
+                        .attrs
+                        .iter()
+                        .filter(|attr| !attr.path().is_ident("doc"))
+                        .map(|attr| path_to_string(attr.path()))
+                        .collect(),
+                    status: TestStatus::Pending,
+                    duration: None,
+                    location: Some(line_column(func.sig.ident.span())),
+                    panic: None,
+                    output: String::new(),
+                    flakiness: None,
+                    stress: None,
+                    baseline: None,
+                });
+            }
+            Item::Mod(module) => {
+                let name = module.ident.to_string();
+                let mut module_path = scope.module_path.clone();
+                module_path.push(name.clone());
+
+                match &module.content {
+                    Some((_, items)) => {
+                        let dir = scope.dir.join(&name);
+                        let inner = Scope {
+                            module_path,
+                            dir: dir.clone(),
+                            path_base: dir,
+                        };
+                        collect_tests(items, &inner, tests, submodules);
+                    }
+                    None => {
+                        if let Some(path) = path_attribute(&module.attrs) {
+                            submodules.push(ModuleFile {
+                                path: scope.path_base.join(path),
+                                module_path,
+                                mod_rs: true,
+                            });
+                        } else {
+                            let file = scope.dir.join(format!("{}.rs", name));
+                            let mod_rs = !file.exists();
+                            submodules.push(ModuleFile {
+                                path: if mod_rs {
+                                    scope.dir.join(&name).join("mod.rs")
+                                } else {
+                                    file
+                                },
+                                module_path,
+                                mod_rs,
+                            });
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Matches `#[test]` as well as runtime-provided test attributes such as
+/// `#[tokio::test]` and `#[async_std::test]`.
+fn is_test_attribute(attr: &Attribute) -> bool {
+    let path = attr.path();
+    path.is_ident("test")
+        || (path.segments.len() > 1 && path.segments.last().is_some_and(|s| s.ident == "test"))
+}
+
+fn path_attribute(attrs: &[Attribute]) -> Option<String> {
+    attrs.iter().find_map(|attr| match &attr.meta {
+        Meta::NameValue(meta) if meta.path.is_ident("path") => match &meta.value {
+            Expr::Lit(expr) => match &expr.lit {
+                Lit::Str(value) => Some(value.value()),
+                _ => None,
+            },
+            _ => None,
+        },
+        _ => None,
+    })
+}
+
+/// One-based line and column of a span.
+fn line_column(span: proc_macro2::Span) -> (usize, usize) {
+    let start = span.start();
+    (start.line, start.column + 1)
+}
+
+fn path_to_string(path: &syn::Path) -> String {
+    path.segments
+        .iter()
+        .map(|segment| segment.ident.to_string())
+        .collect::<Vec<_>>()
+        .join("::")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Writes the files of a crate under a fresh temporary directory and
+    /// returns the path of its `src`.
+    fn write_crate(name: &str, files: &[(&str, &str)]) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("lazytestr-{}-{}", name, std::process::id()))
+            .join("src");
+        let _ = fs::remove_dir_all(&dir);
+        for (path, content) in files {
+            let path = dir.join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        }
+        dir
+    }
+
+    /// The tests found from `lib.rs`, as (file relative to `src`, names),
+    /// sorted by file.
+    fn scan(src: &Path) -> Vec<(String, Vec<String>)> {
+        let mut files: Vec<(String, Vec<String>)> = scan_crate(&src.join("lib.rs"))
+            .unwrap()
+            .into_iter()
+            .map(|file| {
+                let path = file.path.strip_prefix(src).unwrap();
+                let names = file.tests.into_iter().map(|test| test.name).collect();
+                (path.to_string_lossy().replace('\\', "/"), names)
+            })
+            .collect();
+        files.sort();
+        fs::remove_dir_all(src.parent().unwrap()).unwrap();
+        files
+    }
+
+    fn file(path: &str, names: &[&str]) -> (String, Vec<String>) {
+        (
+            path.to_string(),
+            names.iter().map(|name| name.to_string()).collect(),
+        )
+    }
+
+    #[test]
+    fn module_files() {
+        let src = write_crate(
+            "module-files",
+            &[
+                ("lib.rs", "mod a;\nmod b;\n#[test]\nfn root() {}"),
+                ("a.rs", "mod c;\n#[test]\nfn t() {}"),
+                ("a/c.rs", "#[test]\nfn t() {}"),
+                ("b/mod.rs", "mod d;\n#[test]\nfn t() {}"),
+                ("b/d.rs", "#[test]\nfn t() {}"),
+            ],
+        );
+        assert_eq!(
+            scan(&src),
+            [
+                file("a.rs", &["a::t"]),
+                file("a/c.rs", &["a::c::t"]),
+                file("b/d.rs", &["b::d::t"]),
+                file("b/mod.rs", &["b::t"]),
+                file("lib.rs", &["root"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn path_attributes() {
+        let src = write_crate(
+            "path-attributes",
+            &[
+                ("lib.rs", "#[path = \"other/x.rs\"]\nmod x;\nmod y;"),
+                // A file named by `#[path]` resolves its modules like a mod.rs.
+                ("other/x.rs", "mod z;\n#[test]\nfn t() {}"),
+                ("other/z.rs", "#[test]\nfn t() {}"),
+                (
+                    "y.rs",
+                    "#[path = \"w.rs\"]\nmod w;\nmod inner {\n    #[path = \"v.rs\"]\n    mod v;\n}",
+                ),
+                ("w.rs", "#[test]\nfn t() {}"),
+                ("y/inner/v.rs", "#[test]\nfn t() {}"),
+            ],
+        );
+        assert_eq!(
+            scan(&src),
+            [
+                file("lib.rs", &[]),
+                file("other/x.rs", &["x::t"]),
+                file("other/z.rs", &["x::z::t"]),
+                file("w.rs", &["y::w::t"]),
+                file("y.rs", &[]),
+                file("y/inner/v.rs", &["y::inner::v::t"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn inline_modules() {
+        let src = write_crate(
+            "inline-modules",
+            &[
+                (
+                    "lib.rs",
+                    "mod tests {\n    mod nested {\n        #[test]\n        fn t() {}\n    }\n    mod file;\n}",
+                ),
+                ("tests/file.rs", "#[test]\nfn t() {}"),
+            ],
+        );
+        assert_eq!(
+            scan(&src),
+            [
+                file("lib.rs", &["tests::nested::t"]),
+                file("tests/file.rs", &["tests::file::t"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_attributes() {
+        let src = write_crate(
+            "test-attributes",
+            &[(
+                "lib.rs",
+                "#[test]\nfn plain() {}\n\
+                 #[tokio::test]\nasync fn tokio() {}\n\
+                 #[x::test]\nfn other_runtime() {}\n\
+                 #[testing]\nfn not_a_test() {}\n\
+                 #[cfg(test)]\nfn helper() {}",
+            )],
+        );
+        assert_eq!(
+            scan(&src),
+            [file("lib.rs", &["plain", "tokio", "other_runtime"])]
+        );
+    }
+}
@@ -0,0 +1,161 @@
+use serde_json::Value;
+use std::error::Error;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum TargetKind {
+    Lib,
+    Bin,
+    Test,
+    Example,
+    Bench,
+}
+
+impl fmt::Display for TargetKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TargetKind::Lib => write!(f, "lib"),
+            TargetKind::Bin => write!(f, "bin"),
+            TargetKind::Test => write!(f, "test"),
+            TargetKind::Example => write!(f, "example"),
+            TargetKind::Bench => write!(f, "bench"),
+        }
+    }
+}
+
+/// A cargo target that can contain tests.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Target {
+    pub name: String,
+    pub kind: TargetKind,
+    pub src_path: PathBuf,
+}
+
+impl Target {
+    /// The `cargo test` arguments that select exactly this target.
+    pub fn cargo_args(&self) -> Vec<String> {
+        match self.kind {
+            TargetKind::Lib => vec!["--lib".to_string()],
+            kind => vec![format!("--{}", kind), self.name.clone()],
+        }
+    }
+}
+
+pub struct Package {
+    pub name: String,
+    /// The directory of the package's manifest, which its tests run in.
+    pub dir: PathBuf,
+    pub targets: Vec<Target>,
+}
+
+/// The workspace members and their test targets as reported by `cargo metadata`.
+pub struct Workspace {
+    pub root: PathBuf,
+    pub target_dir: PathBuf,
+    pub packages: Vec<Package>,
+}
+
+impl Workspace {
+    pub fn load(dir: &Path) -> Result<Self, Box<dyn Error>> {
+        let output = Command::new("cargo")
+            .current_dir(dir)
+            .args(["metadata", "--format-version", "1", "--no-deps"])
+            .output()?;
+        if !output.status.success() {
+            return Err(format!(
+                "cargo metadata failed: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            )
+            .into());
+        }
+
+        let metadata: Value = serde_json::from_slice(&output.stdout)?;
+        let root = metadata["workspace_root"]
+            .as_str()
+            .map(PathBuf::from)
+            .ok_or("cargo metadata did not report a workspace root")?;
+        let target_dir = metadata["target_directory"]
+            .as_str()
+            .map(PathBuf::from)
+            .unwrap_or_else(|| root.join("target"));
+        let members: Vec<&Value> = metadata["workspace_members"]
+            .as_array()
+            .map(|members| members.iter().collect())
+            .unwrap_or_default();
+
+        let mut packages = Vec::new();
+        for package in metadata["packages"].as_array().into_iter().flatten() {
+            if !members.contains(&&package["id"]) {
+                continue;
+            }
+
+            let mut targets = Vec::new();
+            for target in package["targets"].as_array().into_iter().flatten() {
+                // Examples and benches are only tested when asked for explicitly.
+                if target["test"] != true {
+                    continue;
+                }
+                let Some(kind) = target["kind"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .find_map(|kind| target_kind(kind.as_str()?))
+                else {
+                    continue;
+                };
+                if let (Some(name), Some(src_path)) =
+                    (target["name"].as_str(), target["src_path"].as_str())
+                {
+                    targets.push(Target {
+                        name: name.to_string(),
+                        kind,
+                        src_path: PathBuf::from(src_path),
+                    });
+                }
+            }
+
+            if let (Some(name), Some(manifest)) =
+                (package["name"].as_str(), package["manifest_path"].as_str())
+            {
+                packages.push(Package {
+                    name: name.to_string(),
+                    dir: Path::new(manifest)
+                        .parent()
+                        .map_or_else(|| root.clone(), Path::to_path_buf),
+                    targets,
+                });
+            }
+        }
+        packages.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(Workspace {
+            root,
+            target_dir,
+            packages,
+        })
+    }
+
+    /// Finds the package and target built from the given crate root.
+    pub fn find_target(&self, src_path: &Path) -> Option<(&Package, &Target)> {
+        self.packages.iter().find_map(|package| {
+            package
+                .targets
+                .iter()
+                .find(|target| target.src_path == src_path)
+                .map(|target| (package, target))
+        })
+    }
+}
+
+fn target_kind(kind: &str) -> Option<TargetKind> {
+    match kind {
+        "lib" | "rlib" | "dylib" | "cdylib" | "staticlib" | "proc-macro" => Some(TargetKind::Lib),
+        "bin" => Some(TargetKind::Bin),
+        "test" => Some(TargetKind::Test),
+        "example" => Some(TargetKind::Example),
+        "bench" => Some(TargetKind::Bench),
+        _ => None,
+    }
+}